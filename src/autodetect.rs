@@ -0,0 +1,157 @@
+use std::io;
+
+use crate::config::AppConfig;
+use crate::setup::{self, Candidate};
+use crate::source::SensorSource;
+
+// Known CPU drivers and the sensor label to show, in order of preference
+const CPU_SENSORS: &[(&str, &str)] = &[
+    ("k10temp", "tctl"),
+    ("zenpower", "tdie"),
+    ("zenpower", "tctl"),
+    ("coretemp", "package id 0"),
+    ("cpu_thermal", "temp1"),
+];
+
+// Known GPU drivers, in order of preference
+const GPU_SENSORS: &[(&str, &str)] = &[
+    ("amdgpu", "edge"),
+    ("amdgpu", "junction"),
+    ("radeon", "temp1"),
+    ("nouveau", "temp1"),
+    ("xe", "pkg"),
+];
+
+// Shown in the GPU digits for systems whose GPU reports no temperature
+// through hwmon (e.g. NVIDIA's proprietary driver)
+const GPU_FALLBACK: (&str, &str) = ("nvme", "composite");
+
+/// Builds a config from the first known CPU and GPU sensors present, for
+/// running without a config file. A role with no known sensor is left blank,
+/// and the config's warnings say so.
+pub fn detect(sensors: &dyn SensorSource) -> io::Result<AppConfig> {
+    detect_from(&setup::candidates(sensors))
+}
+
+fn detect_from(candidates: &[Candidate]) -> io::Result<AppConfig> {
+    let mut warnings = Vec::new();
+    let cpu = pick(candidates, CPU_SENSORS);
+    if cpu.is_none() {
+        warnings.push("No known CPU sensor found, the CPU digits stay blank".to_string());
+    }
+
+    // APUs' integrated GPUs only report an edge temperature, so any discrete
+    // card, which also reports junction, goes first
+    let discrete: Vec<Candidate> = candidates.iter().filter(|c| !is_integrated(c, candidates)).cloned().collect();
+    let mut gpu = pick(&discrete, GPU_SENSORS).or_else(|| pick(candidates, GPU_SENSORS));
+    if gpu.is_none() {
+        gpu = pick(candidates, &[GPU_FALLBACK]);
+        match gpu {
+            Some(fallback) => warnings.push(format!(
+                "No known GPU sensor found, showing the {} {} temperature in the GPU digits instead",
+                fallback.chip, fallback.label)),
+            None => warnings.push("No known GPU sensor found, the GPU digits stay blank".to_string()),
+        }
+    }
+
+    if cpu.is_none() && gpu.is_none() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No known CPU or GPU sensor found to pick automatically"));
+    }
+    let mut config = AppConfig::parse_toml(&setup::render_config(cpu, gpu, candidates))?;
+    config.warnings.extend(warnings);
+    Ok(config)
+}
+
+fn pick<'a>(candidates: &'a [Candidate], table: &[(&str, &str)]) -> Option<&'a Candidate> {
+    table.iter().find_map(|&(device, label)| {
+        candidates.iter().find(|c| c.device == device && c.label.to_lowercase() == label)
+    })
+}
+
+fn is_integrated(gpu: &Candidate, all: &[Candidate]) -> bool {
+    gpu.device == "amdgpu" && !all.iter().any(|c| c.chip == gpu.chip && c.label.eq_ignore_ascii_case("junction"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DeviceType;
+    use crate::setup::mock::candidate;
+
+    const AMD_CPU: Option<(&str, &str)> = Some(("1022", "14e3"));
+    const AMD_GPU: Option<(&str, &str)> = Some(("1002", "7550"));
+
+    #[test]
+    fn test_detect_prefers_table_order() {
+        let candidates = [
+            candidate("nvme-pci-0100", None, "Composite"),
+            candidate("amdgpu-pci-0300", AMD_GPU, "junction"),
+            candidate("amdgpu-pci-0300", AMD_GPU, "edge"),
+            candidate("k10temp-pci-00c3", AMD_CPU, "Tccd1"),
+            candidate("k10temp-pci-00c3", AMD_CPU, "Tctl"),
+        ];
+
+        let config = detect_from(&candidates).unwrap();
+
+        let display = &config.displays[0];
+        assert_eq!(("k10temp", "tctl"), (display.cpu_device.as_str(), display.cpu_temp_type.as_str()));
+        assert_eq!(("amdgpu", "edge"), (display.gpu_device.as_str(), display.gpu_temp_type.as_str()));
+        assert!(config.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_detect_prefers_the_discrete_gpu() {
+        let igpu = candidate("amdgpu-pci-0e00", Some(("1002", "164e")), "edge");
+        let candidates = [
+            candidate("k10temp-pci-00c3", AMD_CPU, "Tctl"),
+            igpu.clone(),
+            candidate("nouveau-pci-0100", None, "temp1"),
+        ];
+        let display = detect_from(&candidates).unwrap().displays.remove(0);
+        assert_eq!("nouveau", display.gpu_device);
+
+        // With an iGPU and a dGPU of the same driver, the IDs say which one
+        // was picked
+        let candidates = [
+            candidate("k10temp-pci-00c3", AMD_CPU, "Tctl"),
+            igpu.clone(),
+            candidate("amdgpu-pci-0300", AMD_GPU, "edge"),
+            candidate("amdgpu-pci-0300", AMD_GPU, "junction"),
+        ];
+        let display = detect_from(&candidates).unwrap().displays.remove(0);
+        assert_eq!((Some(0x1002), Some(0x7550)), (display.gpu_ids.vendor, display.gpu_ids.device));
+
+        // The iGPU is still better than nothing
+        let display = detect_from(&candidates[..2]).unwrap().displays.remove(0);
+        assert_eq!(("amdgpu", "edge"), (display.gpu_device.as_str(), display.gpu_temp_type.as_str()));
+    }
+
+    #[test]
+    fn test_detect_falls_back_and_leaves_roles_blank() {
+        let candidates = [
+            candidate("coretemp-isa-0000", None, "Package id 0"),
+            candidate("coretemp-isa-0000", None, "Core 0"),
+            candidate("nvme-pci-0100", None, "Composite"),
+        ];
+        let config = detect_from(&candidates).unwrap();
+        let display = &config.displays[0];
+        assert_eq!(("coretemp", "package id 0"), (display.cpu_device.as_str(), display.cpu_temp_type.as_str()));
+        assert_eq!(("nvme", "composite"), (display.gpu_device.as_str(), display.gpu_temp_type.as_str()));
+        assert_eq!(
+            vec!["No known GPU sensor found, showing the nvme-pci-0100 Composite temperature in the GPU digits instead"],
+            config.warnings
+        );
+
+        // Without a GPU at all the GPU digits stay blank
+        let config = detect_from(&candidates[..2]).unwrap();
+        assert_eq!("", config.displays[0].gpu_device);
+        assert_eq!("none", config.displays[0].format_device_info(DeviceType::Gpu));
+        assert_eq!(vec!["No known GPU sensor found, the GPU digits stay blank"], config.warnings);
+
+        let config = detect_from(&[candidate("amdgpu-pci-0300", AMD_GPU, "edge")]).unwrap();
+        assert_eq!(("", "amdgpu"), (config.displays[0].cpu_device.as_str(), config.displays[0].gpu_device.as_str()));
+
+        let err = detect_from(&[candidate("acpitz-acpi-0", None, "temp1")]).unwrap_err();
+        assert_eq!("No known CPU or GPU sensor found to pick automatically", err.to_string());
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::source::Backend;
+
+/// Shows CPU and GPU temperatures on the Antec Flux Pro case display.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Config file to use instead of searching the usual locations
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Only print warnings and errors
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Read sensors through libsensors (the default), or straight from sysfs
+    #[arg(long, global = true, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Where sysfs is mounted, e.g. the host's /sys inside a container.
+    /// Implies --backend sysfs
+    #[arg(long, global = true, value_name = "PATH")]
+    pub sysfs_root: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Running is the default, so its options work without `run` as well
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Show temperatures on the display until stopped (the default)
+    Run(RunArgs),
+    /// List the temperature sensors that can be used in the config
+    ListSensors(ListArgs),
+    /// Check the config file and the sensors it names, then exit
+    CheckConfig(CheckArgs),
+    /// Send one set of temperatures to the display, then exit
+    Send(SendArgs),
+    /// Pick the sensors interactively and write the config file
+    Setup,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Args)]
+pub struct RunArgs {
+    /// Wait for the display to be connected instead of exiting, for at most
+    /// SECS seconds if given
+    #[arg(long, value_name = "SECS", num_args = 0..=1, require_equals = true)]
+    pub wait_for_device: Option<Option<u64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Args)]
+pub struct CheckArgs {
+    /// Only warn about a sensor that isn't found or is ambiguous, as long as
+    /// the display has its other one, as running does
+    #[arg(long)]
+    pub allow_missing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Args)]
+pub struct ListArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Args)]
+pub struct SendArgs {
+    /// CPU temperature in °C, or - to leave it blank
+    #[arg(allow_hyphen_values = true, required_unless_present = "raw")]
+    pub cpu: Option<String>,
+
+    /// GPU temperature in °C, or - to leave it blank
+    #[arg(allow_hyphen_values = true, required_unless_present = "raw")]
+    pub gpu: Option<String>,
+
+    /// Send this frame, given in hex, instead of temperatures
+    #[arg(long, value_name = "HEX", conflicts_with_all = ["cpu", "gpu"])]
+    pub raw: Option<String>,
+
+    /// Display to send to, by its name in the config. Defaults to the first
+    #[arg(long, value_name = "NAME")]
+    pub display: Option<String>,
+}
+
+impl Cli {
+    /// Parses the command line, exiting with the usage on errors.
+    pub fn parse_args() -> Self {
+        Self::parse().validate().unwrap_or_else(|e| e.exit())
+    }
+
+    // The global options go before or after any subcommand, but the run
+    // options only before `run` or none
+    fn validate(mut self) -> Result<Self, clap::Error> {
+        if self.run == RunArgs::default() {
+            return Ok(self);
+        }
+        match &mut self.command {
+            Some(Command::Run(args)) if *args == RunArgs::default() => *args = self.run.clone(),
+            None | Some(Command::Run(_)) => {}
+            Some(_) => {
+                let message = "--wait-for-device only applies when running the display";
+                return Err(<Self as CommandFactory>::command().error(ErrorKind::ArgumentConflict, message));
+            }
+        }
+        Ok(self)
+    }
+
+    /// The command to run, `run` if none was given.
+    pub fn command(&self) -> Command {
+        self.command.clone().unwrap_or_else(|| Command::Run(self.run.clone()))
+    }
+}
+
+/// Parses a temperature given on the command line, where "-" means blank.
+pub fn parse_temperature(s: &str) -> Result<Option<f64>, String> {
+    if s == "-" {
+        return Ok(None);
+    }
+    s.parse().map(Some).map_err(|_| format!("Invalid temperature '{}', expected e.g. 45.5 or -", s))
+}
+
+/// Parses a frame given in hex, ignoring spaces and colons between bytes.
+pub fn parse_hex_frame(s: &str) -> Result<Vec<u8>, String> {
+    let digits: String = s.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    // Checked first, so the pairs below can't split a multi-byte character
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid frame '{}', expected hex digits", s));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("Invalid frame '{}', expected an even number of hex digits", s));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["antec-flux-pro-display"].iter().chain(args)).and_then(Cli::validate)
+    }
+
+    #[test]
+    fn test_run_is_the_default() {
+        let cli = parse(&[]).unwrap();
+        assert!(matches!(cli.command(), Command::Run(RunArgs { wait_for_device: None })));
+
+        let cli = parse(&["--wait-for-device", "--config", "/tmp/a.toml"]).unwrap();
+        assert!(matches!(cli.command(), Command::Run(RunArgs { wait_for_device: Some(None) })));
+        assert_eq!(Some(PathBuf::from("/tmp/a.toml")), cli.config);
+
+        let cli = parse(&["run", "--wait-for-device=30", "-q"]).unwrap();
+        assert!(matches!(cli.command(), Command::Run(RunArgs { wait_for_device: Some(Some(30)) })));
+        assert!(cli.quiet);
+
+        assert!(parse(&["--wait-for-device=soon"]).is_err());
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert!(matches!(parse(&["list-sensors"]).unwrap().command(), Command::ListSensors(ListArgs { format: Format::Text })));
+        assert!(matches!(parse(&["list-sensors", "--format", "json"]).unwrap().command(), Command::ListSensors(ListArgs { format: Format::Json })));
+        assert!(parse(&["list-sensors", "--format", "yaml"]).is_err());
+        assert!(matches!(parse(&["check-config", "--config=b.conf"]).unwrap().command(), Command::CheckConfig(CheckArgs { allow_missing: false })));
+        assert!(matches!(parse(&["check-config", "--allow-missing"]).unwrap().command(), Command::CheckConfig(CheckArgs { allow_missing: true })));
+        assert!(matches!(parse(&["setup"]).unwrap().command(), Command::Setup));
+
+        let Command::Send(send) = parse(&["send", "45.5", "-", "--display", "top"]).unwrap().command() else {
+            panic!("expected send");
+        };
+        assert_eq!((Some("45.5"), Some("-")), (send.cpu.as_deref(), send.gpu.as_deref()));
+        assert_eq!(Some("top".to_string()), send.display);
+
+        assert!(parse(&["send", "--raw", "55aa"]).is_ok());
+        assert!(parse(&["send", "45"]).is_err());
+        assert!(parse(&["send", "45", "50", "--raw", "55aa"]).is_err());
+    }
+
+    #[test]
+    fn test_global_options_before_subcommands() {
+        let cli = parse(&["-q", "list-sensors"]).unwrap();
+        assert!(cli.quiet);
+        assert!(matches!(cli.command(), Command::ListSensors(_)));
+
+        let cli = parse(&["--backend", "sysfs", "--sysfs-root", "/host/sys", "list-sensors", "--format", "json"]).unwrap();
+        assert_eq!((Some(Backend::Sysfs), Some(PathBuf::from("/host/sys"))), (cli.backend, cli.sysfs_root));
+
+        let cli = parse(&["-c", "/x.toml", "setup"]).unwrap();
+        assert!(matches!(cli.command(), Command::Setup));
+        assert_eq!(Some(PathBuf::from("/x.toml")), cli.config);
+        let cli = parse(&["--config", "/x.toml", "check-config"]).unwrap();
+        assert_eq!(Some(PathBuf::from("/x.toml")), cli.config);
+
+        let cli = parse(&["--wait-for-device=5", "run"]).unwrap();
+        assert!(matches!(cli.command(), Command::Run(RunArgs { wait_for_device: Some(Some(5)) })));
+
+        // Run options make no sense for the other subcommands
+        let err = parse(&["--wait-for-device", "list-sensors"]).unwrap_err();
+        assert_eq!(ErrorKind::ArgumentConflict, err.kind());
+        assert!(parse(&["--wait-for-device", "send", "40", "50"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_temperature() {
+        assert_eq!(Ok(Some(45.5)), parse_temperature("45.5"));
+        assert_eq!(Ok(Some(-3.0)), parse_temperature("-3"));
+        assert_eq!(Ok(None), parse_temperature("-"));
+        assert!(parse_temperature("hot").is_err());
+    }
+
+    #[test]
+    fn test_parse_hex_frame() {
+        assert_eq!(Ok(vec![0x55, 0xaa, 0x01]), parse_hex_frame("55aa01"));
+        assert_eq!(Ok(vec![0x55, 0xaa, 0x01]), parse_hex_frame("0x55 AA:01"));
+        assert!(parse_hex_frame("55a").is_err());
+        assert!(parse_hex_frame("zz").is_err());
+        assert!(parse_hex_frame("+1").is_err());
+        assert_eq!(Err("Invalid frame 'aé1', expected hex digits".to_string()), parse_hex_frame("aé1"));
+        assert!(parse_hex_frame("éé").is_err());
+    }
+}
@@ -0,0 +1,1177 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::listing::{self, Bus};
+use crate::protocol::{Overflow, Resolution};
+use crate::usb::UsbTarget;
+
+/// Sensor mapping and USB selection for one case display.
+#[derive(Debug, Clone)]
+pub struct DisplayConfig {
+    pub name: String,
+    pub cpu_device: String,
+    pub cpu_temp_type: String,
+    pub cpu_ids: PciIds,
+    pub cpu_match: MatchMode,
+    /// `cpu_device` and `cpu_temp_type` compiled, when `cpu_match` is
+    /// MatchMode::Regex
+    pub cpu_regexes: Option<SensorRegexes>,
+    /// Bus the CPU sensor's chip must be on
+    pub cpu_bus: Option<Bus>,
+    /// PCI address the CPU sensor's chip must be on, e.g. "0000:00:18.3"
+    pub cpu_pci_address: Option<String>,
+    /// sysfs device (or hwmon directory) the CPU sensor's chip must belong to
+    pub cpu_hwmon_device: Option<PathBuf>,
+    pub gpu_device: String,
+    pub gpu_temp_type: String,
+    pub gpu_ids: PciIds,
+    pub gpu_match: MatchMode,
+    pub gpu_regexes: Option<SensorRegexes>,
+    pub gpu_bus: Option<Bus>,
+    pub gpu_pci_address: Option<String>,
+    pub gpu_hwmon_device: Option<PathBuf>,
+    pub units: Units,
+    pub overflow: Overflow,
+    pub usb: UsbTarget,
+}
+
+#[derive(Debug)]
+pub struct AppConfig {
+    pub displays: Vec<DisplayConfig>,
+    pub update_interval: u64,
+    /// Problems that don't stop the config from loading, e.g. unknown keys.
+    pub warnings: Vec<String>,
+}
+
+/// Temperature scale shown on the display. Sensors always report Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Units {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Units {
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            Units::Celsius => celsius,
+            Units::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// Fahrenheit is shown in whole degrees, since everyday CPU and GPU
+    /// temperatures are above 99.9°F.
+    pub fn resolution(self) -> Resolution {
+        match self {
+            Units::Celsius => Resolution::Tenths,
+            Units::Fahrenheit => Resolution::Whole,
+        }
+    }
+}
+
+/// PCI IDs a sensor's device must have, compared exactly. IDs that aren't
+/// set match any device. For HID devices, such as USB liquid coolers, the
+/// vendor and device are the USB IDs, and they have no subsystem IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PciIds {
+    pub vendor: Option<u16>,
+    pub device: Option<u16>,
+    /// Board partner and model, e.g. to tell a partner card from an otherwise
+    /// identical reference one
+    pub subsystem_vendor: Option<u16>,
+    pub subsystem_device: Option<u16>,
+}
+
+impl PciIds {
+    pub fn is_empty(&self) -> bool {
+        *self == PciIds::default()
+    }
+}
+
+// As "[ID: 1002:*]", with the subsystem IDs only if they're set
+impl fmt::Display for PciIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = |id: Option<u16>| id.map_or("*".to_string(), |id| format!("{:04x}", id));
+        write!(f, "[ID: {}:{}]", id(self.vendor), id(self.device))?;
+        if self.subsystem_vendor.is_some() || self.subsystem_device.is_some() {
+            write!(f, " [subsystem: {}:{}]", id(self.subsystem_vendor), id(self.subsystem_device))?;
+        }
+        Ok(())
+    }
+}
+
+/// How `device` and `temp_type` select a sensor. Names and labels are
+/// compared ignoring case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// The chip name or label starts with the configured value
+    #[default]
+    Prefix,
+    /// The chip name (with or without the bus part) or label is the value
+    Exact,
+    /// The value is a regular expression matching the whole name or label
+    Regex,
+}
+
+/// A sensor's `device` and `temp_type` patterns for MatchMode::Regex,
+/// compiled when the config is loaded.
+#[derive(Debug, Clone)]
+pub struct SensorRegexes {
+    pub device: Regex,
+    pub temp_type: Regex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    Cpu,
+    Gpu,
+}
+
+const CONFIG_DIR: &str = "/etc/antec-flux-pro-display";
+
+const UPDATE_INTERVAL_RANGE: RangeInclusive<u64> = 100..=3_600_000;
+// Endpoint 0 is the control endpoint, and OUT endpoints stop at 15
+const ENDPOINT_RANGE: RangeInclusive<u8> = 0x01..=0x0f;
+
+// Keys understood by the legacy key=value format
+const LEGACY_KEYS: &[&str] = &[
+    "cpu_device", "cpu_temp_type", "cpu_vendor_id", "cpu_device_id", "cpu_subsystem_vendor_id", "cpu_subsystem_device_id",
+    "cpu_match", "cpu_bus", "cpu_pci_address", "cpu_hwmon_device",
+    "gpu_device", "gpu_temp_type", "gpu_vendor_id", "gpu_device_id", "gpu_subsystem_vendor_id", "gpu_subsystem_device_id",
+    "gpu_match", "gpu_bus", "gpu_pci_address", "gpu_hwmon_device",
+    "units", "overflow", "endpoint",
+    "usb_vendor_id", "usb_product_id", "usb_bus", "usb_port", "usb_serial",
+];
+
+/// Environment variable naming the config file, checked after `--config`.
+const CONFIG_ENV: &str = "ANTEC_FLUX_CONFIG";
+
+/// Config files looked for when no path is given, in order: the user's
+/// `$XDG_CONFIG_HOME` (or `~/.config`), then the system-wide directory. In
+/// each, `config.toml` wins over the legacy `config.conf`.
+pub fn search_paths(var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    let user_dir = var("XDG_CONFIG_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")));
+
+    user_dir.map(|dir| dir.join("antec-flux-pro-display"))
+        .into_iter()
+        .chain([PathBuf::from(CONFIG_DIR)])
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config.conf")])
+        .collect()
+}
+
+/// The config file named by `--config` or `ANTEC_FLUX_CONFIG`, if any.
+pub fn explicit_path(cli_path: Option<&Path>, var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    cli_path.map(Path::to_path_buf)
+        .or_else(|| var(CONFIG_ENV).filter(|p| !p.is_empty()).map(PathBuf::from))
+}
+
+/// The system-wide config file, used by the packaged service.
+pub fn system_path() -> PathBuf {
+    Path::new(CONFIG_DIR).join("config.toml")
+}
+
+/// Picks the config file: `cli_path` from `--config` if given, then the
+/// `ANTEC_FLUX_CONFIG` environment variable, then the first of
+/// [`search_paths`] that exists.
+pub fn find_config(cli_path: Option<&Path>, var: impl Fn(&str) -> Option<OsString>) -> io::Result<PathBuf> {
+    if let Some(path) = explicit_path(cli_path, &var) {
+        return match path.exists() {
+            true => Ok(path),
+            false => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Configuration file {} not found", path.display())
+            )),
+        };
+    }
+
+    let candidates = search_paths(var);
+    candidates.iter()
+        .find(|path| path.exists())
+        .cloned()
+        .ok_or_else(|| {
+            let searched: Vec<_> = candidates.iter().map(|p| p.display().to_string()).collect();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No configuration file found, looked for {}", searched.join(", "))
+            )
+        })
+}
+
+/// Compiles a `match = "regex"` pattern. It has to match the whole name or
+/// label, ignoring case.
+pub fn sensor_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!("^(?:{})$", pattern)).case_insensitive(true).build()
+}
+
+impl AppConfig {
+    /// Loads the config file at `path`. Files ending in `.conf` are read as
+    /// the legacy key=value format, anything else as TOML.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let config_str = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "conf") {
+            Self::parse_legacy(&config_str)
+        } else {
+            Self::parse_toml(&config_str)
+        }
+    }
+
+    // Top-level keys and [cpu]/[gpu]/[usb] tables apply to every display.
+    // Each [[display]] adds a display, overriding any of those. Without
+    // [[display]] entries there is a single display.
+    pub fn parse_toml(config_str: &str) -> io::Result<Self> {
+        let mut warnings = Vec::new();
+        let deserializer = toml::Deserializer::parse(config_str).map_err(invalid_data)?;
+        let file: TomlConfig = serde_ignored::deserialize(deserializer, |path| {
+            warnings.push(format!("Unknown key '{}' in config, ignoring it", path));
+        }).map_err(invalid_data)?;
+
+        let update_interval = match &file.update_interval {
+            Some(interval) => {
+                check_range(config_str, "update_interval", interval, &UPDATE_INTERVAL_RANGE)?;
+                *interval.get_ref()
+            }
+            None => 1000,
+        };
+
+        let displays = if file.display.is_empty() {
+            vec![DisplayConfig::from_toml(config_str, &file.defaults(), None)?]
+        } else {
+            file.display.iter()
+                .map(|display| DisplayConfig::from_toml(config_str, &file.defaults(), Some(display)))
+                .collect::<io::Result<_>>()?
+        };
+
+        check_distinct_displays(&displays)?;
+        Ok(AppConfig {
+            displays,
+            update_interval,
+            warnings,
+        })
+    }
+
+    // Keys before the first [section] apply to every display. Each section
+    // adds a display, overriding those keys. Without sections there is a
+    // single display.
+    pub fn parse_legacy(config_str: &str) -> io::Result<Self> {
+        let mut global = HashMap::new();
+        let mut sections: Vec<(String, HashMap<String, String>)> = Vec::new();
+
+        let mut warnings = Vec::new();
+
+        for (number, line) in config_str.lines().enumerate() {
+            // Skip empty lines and comments
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                sections.push((name.trim().to_string(), HashMap::new()));
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                let key = key.trim();
+                let known = LEGACY_KEYS.contains(&key) || (key == "update_interval" && sections.is_empty());
+                if !known {
+                    warnings.push(format!("Unknown key '{}' at line {} in config, ignoring it", key, number + 1));
+                }
+                let config_map = match sections.last_mut() {
+                    Some((_, section)) => section,
+                    None => &mut global,
+                };
+                config_map.insert(key.to_string(), value.trim().to_string());
+            }
+        }
+
+        // Update interval is optional, default to 1000ms if not found
+        let update_interval = match global.get("update_interval") {
+            Some(s) => s.parse()
+                .ok()
+                .filter(|ms| UPDATE_INTERVAL_RANGE.contains(ms))
+                .ok_or_else(|| invalid_data(format!(
+                    "Invalid update_interval '{}' in config, expected {} to {} milliseconds",
+                    s, UPDATE_INTERVAL_RANGE.start(), UPDATE_INTERVAL_RANGE.end())))?,
+            None => 1000,
+        };
+
+        let displays = if sections.is_empty() {
+            vec![DisplayConfig::from_map("default", &global)?]
+        } else {
+            sections.into_iter()
+                .map(|(name, section)| {
+                    let mut config_map = global.clone();
+                    config_map.extend(section);
+                    DisplayConfig::from_map(&name, &config_map)
+                })
+                .collect::<io::Result<_>>()?
+        };
+
+        check_distinct_displays(&displays)?;
+        Ok(AppConfig {
+            displays,
+            update_interval,
+            warnings,
+        })
+    }
+}
+
+// Two displays that could open the same device would fight over it, the
+// second one failing as busy
+fn check_distinct_displays(displays: &[DisplayConfig]) -> io::Result<()> {
+    for (i, a) in displays.iter().enumerate() {
+        if let Some(b) = displays[i + 1..].iter().find(|b| a.usb.overlaps(&b.usb)) {
+            return Err(invalid_data(format!(
+                "Displays [{}] and [{}] can both select the same display {}, set a different usb bus, port or serial for each",
+                a.name, b.name, a.usb)));
+        }
+    }
+    Ok(())
+}
+
+impl DisplayConfig {
+    fn from_map(name: &str, config_map: &HashMap<String, String>) -> io::Result<Self> {
+        let missing = |key: &str| missing_key(name, key);
+        let invalid = |key: &str, value: &str, expected: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {} '{}' in config, expected {}", key, value, expected)
+            )
+        };
+
+        let match_mode = |key: &str| match config_map.get(key) {
+            None => Ok(MatchMode::Prefix),
+            Some(s) => parse_match_mode(s).ok_or_else(|| invalid(key, s, MATCH_NAMES)),
+        };
+        let patterns = |role: &str, mode: MatchMode| -> io::Result<([String; 2], Option<SensorRegexes>)> {
+            let keys = [format!("{}_device", role), format!("{}_temp_type", role)];
+            let device = config_map.get(&keys[0]).ok_or_else(|| missing(&keys[0]))?;
+            let temp_type = config_map.get(&keys[1]).ok_or_else(|| missing(&keys[1]))?;
+            check_patterns(&keys, [device, temp_type], mode)
+        };
+        let bus = |key: &str| config_map.get(key)
+            .map(|s| parse_bus(s).ok_or_else(|| invalid(key, s, BUS_NAMES)))
+            .transpose();
+        let pci_address = |key: &str| config_map.get(key)
+            .map(|s| parse_pci_address(s).ok_or_else(|| invalid(key, s, PCI_ADDRESS_EXPECTED)))
+            .transpose();
+        // An empty ID, e.g. "gpu_vendor_id=", is the same as leaving it out
+        let pci_ids = |role: &str| -> io::Result<PciIds> {
+            let id = |name: &str| {
+                let key = format!("{}_{}", role, name);
+                config_map.get(&key)
+                    .filter(|s| !s.trim().is_empty())
+                    .map(|s| parse_hex_id(s).ok_or_else(|| invalid(&key, s, HEX_ID_EXPECTED)))
+                    .transpose()
+            };
+            Ok(PciIds {
+                vendor: id("vendor_id")?,
+                device: id("device_id")?,
+                subsystem_vendor: id("subsystem_vendor_id")?,
+                subsystem_device: id("subsystem_device_id")?,
+            })
+        };
+        let hwmon_device = |key: &str| config_map.get(key)
+            .map(|s| parse_device_path(s).ok_or_else(|| invalid(key, s, DEVICE_PATH_EXPECTED)))
+            .transpose();
+
+        // Get required values or return error if not found
+
+        // Required CPU values
+        let cpu_match = match_mode("cpu_match")?;
+        let ([cpu_device, cpu_temp_type], cpu_regexes) = patterns("cpu", cpu_match)?;
+
+        // Optional CPU values (None if not set)
+        let cpu_ids = pci_ids("cpu")?;
+        let cpu_bus = bus("cpu_bus")?;
+        let cpu_pci_address = pci_address("cpu_pci_address")?;
+        let cpu_hwmon_device = hwmon_device("cpu_hwmon_device")?;
+
+        // Required GPU values
+        let gpu_match = match_mode("gpu_match")?;
+        let ([gpu_device, gpu_temp_type], gpu_regexes) = patterns("gpu", gpu_match)?;
+
+        // Optional GPU values (None if not set)
+        let gpu_ids = pci_ids("gpu")?;
+        let gpu_bus = bus("gpu_bus")?;
+        let gpu_pci_address = pci_address("gpu_pci_address")?;
+        let gpu_hwmon_device = hwmon_device("gpu_hwmon_device")?;
+
+        // Units are optional, default to Celsius if not found
+        let units = match config_map.get("units") {
+            None => Units::Celsius,
+            Some(s) => parse_units(s).ok_or_else(|| invalid("units", s, UNITS_NAMES))?,
+        };
+
+        // What to show above 99.9 degrees, default to clamping if not found
+        let overflow = match config_map.get("overflow") {
+            None => Overflow::Clamp,
+            Some(s) => parse_overflow(s).ok_or_else(|| invalid("overflow", s, OVERFLOW_NAMES))?,
+        };
+
+        // Optional USB values, defaulting to the Flux Pro display
+        let mut usb = UsbTarget::default();
+
+        if let Some(s) = config_map.get("usb_vendor_id") {
+            usb.vendor_id = parse_hex_id(s).ok_or_else(|| invalid("usb_vendor_id", s, "e.g. 2022"))?;
+        }
+        if let Some(s) = config_map.get("usb_product_id") {
+            usb.product_id = parse_hex_id(s).ok_or_else(|| invalid("usb_product_id", s, "e.g. 0522"))?;
+        }
+        if let Some(s) = config_map.get("usb_bus") {
+            usb.bus = Some(s.parse().map_err(|_| invalid("usb_bus", s, "a bus number, e.g. 1"))?);
+        }
+        if let Some(s) = config_map.get("usb_port") {
+            usb.port = Some(parse_port(s).ok_or_else(|| invalid("usb_port", s, "port numbers, e.g. 1.4"))?);
+        }
+        usb.serial = config_map.get("usb_serial").cloned();
+
+        // Endpoint is optional, discovered from the USB descriptors if not found
+        if let Some(s) = config_map.get("endpoint") {
+            usb.endpoint = Some(parse_endpoint(s)
+                .filter(|e| ENDPOINT_RANGE.contains(e))
+                .ok_or_else(|| invalid("endpoint", s, "0x01 to 0x0f"))?);
+        }
+
+        Ok(DisplayConfig {
+            name: name.to_string(),
+            cpu_device,
+            cpu_temp_type,
+            cpu_ids,
+            cpu_match,
+            cpu_regexes,
+            cpu_bus,
+            cpu_pci_address,
+            cpu_hwmon_device,
+            gpu_device,
+            gpu_temp_type,
+            gpu_ids,
+            gpu_match,
+            gpu_regexes,
+            gpu_bus,
+            gpu_pci_address,
+            gpu_hwmon_device,
+            units,
+            overflow,
+            usb,
+        })
+    }
+
+    fn from_toml(config_str: &str, defaults: &TomlDisplay, display: Option<&TomlDisplay>) -> io::Result<Self> {
+        let merged = match display {
+            Some(display) => display.or(defaults),
+            None => defaults.clone(),
+        };
+        let name = merged.name.as_str();
+        let missing = |key: &str| missing_key(name, key);
+
+        type Patterns = ([String; 2], Option<SensorRegexes>);
+        let sensor = |role: &str, sensor: &TomlSensor| -> io::Result<(Patterns, PciIds, MatchMode)> {
+            let mode = choice(config_str, &format!("{}.match", role), sensor.match_mode.as_ref(), parse_match_mode, MATCH_NAMES)?
+                .unwrap_or_default();
+            let device = sensor.device.as_ref().ok_or_else(|| missing(&format!("{}.device", role)))?;
+            let temp_type = sensor.temp_type.as_ref().ok_or_else(|| missing(&format!("{}.temp_type", role)))?;
+            // An empty ID is the same as leaving it out, as in the legacy format
+            let id = |key: &str, value: &Option<Spanned<String>>| {
+                let value = value.as_ref().filter(|v| !v.get_ref().trim().is_empty());
+                choice(config_str, &format!("{}.{}", role, key), value, parse_hex_id, HEX_ID_EXPECTED)
+            };
+            let ids = PciIds {
+                vendor: id("vendor_id", &sensor.vendor_id)?,
+                device: id("device_id", &sensor.device_id)?,
+                subsystem_vendor: id("subsystem_vendor_id", &sensor.subsystem_vendor_id)?,
+                subsystem_device: id("subsystem_device_id", &sensor.subsystem_device_id)?,
+            };
+            let keys = [format!("{}.device", role), format!("{}.temp_type", role)];
+            Ok((check_patterns(&keys, [device, temp_type], mode)?, ids, mode))
+        };
+        let (([cpu_device, cpu_temp_type], cpu_regexes), cpu_ids, cpu_match) = sensor("cpu", &merged.cpu)?;
+        let (([gpu_device, gpu_temp_type], gpu_regexes), gpu_ids, gpu_match) = sensor("gpu", &merged.gpu)?;
+        let cpu_bus = choice(config_str, "cpu.bus", merged.cpu.bus.as_ref(), parse_bus, BUS_NAMES)?;
+        let gpu_bus = choice(config_str, "gpu.bus", merged.gpu.bus.as_ref(), parse_bus, BUS_NAMES)?;
+        let cpu_pci_address = choice(config_str, "cpu.pci_address", merged.cpu.pci_address.as_ref(), parse_pci_address, PCI_ADDRESS_EXPECTED)?;
+        let gpu_pci_address = choice(config_str, "gpu.pci_address", merged.gpu.pci_address.as_ref(), parse_pci_address, PCI_ADDRESS_EXPECTED)?;
+        let cpu_hwmon_device = choice(config_str, "cpu.hwmon_device", merged.cpu.hwmon_device.as_ref(), parse_device_path, DEVICE_PATH_EXPECTED)?;
+        let gpu_hwmon_device = choice(config_str, "gpu.hwmon_device", merged.gpu.hwmon_device.as_ref(), parse_device_path, DEVICE_PATH_EXPECTED)?;
+
+        let defaults = UsbTarget::default();
+        let endpoint = match &merged.usb.endpoint {
+            Some(endpoint) => {
+                check_range(config_str, "usb.endpoint", endpoint, &ENDPOINT_RANGE)?;
+                Some(*endpoint.get_ref())
+            }
+            None => None,
+        };
+        let usb = UsbTarget {
+            vendor_id: merged.usb.vendor_id.unwrap_or(defaults.vendor_id),
+            product_id: merged.usb.product_id.unwrap_or(defaults.product_id),
+            bus: merged.usb.bus,
+            port: merged.usb.port,
+            serial: merged.usb.serial,
+            endpoint,
+        };
+
+        Ok(DisplayConfig {
+            name: name.to_string(),
+            cpu_device,
+            cpu_temp_type,
+            cpu_ids,
+            cpu_match,
+            cpu_regexes,
+            cpu_bus,
+            cpu_pci_address,
+            cpu_hwmon_device,
+            gpu_device,
+            gpu_temp_type,
+            gpu_ids,
+            gpu_match,
+            gpu_regexes,
+            gpu_bus,
+            gpu_pci_address,
+            gpu_hwmon_device,
+            units: choice(config_str, "units", merged.units.as_ref(), parse_units, UNITS_NAMES)?.unwrap_or_default(),
+            overflow: choice(config_str, "overflow", merged.overflow.as_ref(), parse_overflow, OVERFLOW_NAMES)?
+                .unwrap_or_default(),
+            usb,
+        })
+    }
+
+    pub fn format_device_info(&self, device_type: DeviceType) -> String {
+        let (name, temp_type, ids, mode) = match device_type {
+            DeviceType::Cpu => (&self.cpu_device, &self.cpu_temp_type, &self.cpu_ids, self.cpu_match),
+            DeviceType::Gpu => (&self.gpu_device, &self.gpu_temp_type, &self.gpu_ids, self.gpu_match),
+        };
+        let (bus, pci_address, hwmon_device) = match device_type {
+            DeviceType::Cpu => (self.cpu_bus, &self.cpu_pci_address, &self.cpu_hwmon_device),
+            DeviceType::Gpu => (self.gpu_bus, &self.gpu_pci_address, &self.gpu_hwmon_device),
+        };
+
+        if name.is_empty() {
+            return "none".to_string();
+        }
+        let mut out = format!("{} (type: {})", name, temp_type);
+        match mode {
+            MatchMode::Prefix => {}
+            MatchMode::Exact => out.push_str(" [exact]"),
+            MatchMode::Regex => out.push_str(" [regex]"),
+        }
+        if let Some(bus) = bus {
+            out.push_str(&format!(" [bus: {}]", bus));
+        }
+        if !ids.is_empty() {
+            out.push_str(&format!(" {}", ids));
+        }
+        if let Some(address) = pci_address {
+            out.push_str(&format!(" [PCI: {}]", address));
+        }
+        if let Some(path) = hwmon_device {
+            out.push_str(&format!(" [device: {}]", path.display()));
+        }
+        out
+    }
+}
+
+// Schema of config.toml. Every key is optional at this stage, since a
+// [[display]] inherits whatever it leaves out from the top level.
+#[derive(Debug, Deserialize)]
+struct TomlConfig {
+    update_interval: Option<Spanned<u64>>,
+    units: Option<Spanned<String>>,
+    overflow: Option<Spanned<String>>,
+    #[serde(default)]
+    cpu: TomlSensor,
+    #[serde(default)]
+    gpu: TomlSensor,
+    #[serde(default)]
+    usb: TomlUsb,
+    #[serde(default)]
+    display: Vec<TomlDisplay>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TomlDisplay {
+    name: String,
+    units: Option<Spanned<String>>,
+    overflow: Option<Spanned<String>>,
+    #[serde(default)]
+    cpu: TomlSensor,
+    #[serde(default)]
+    gpu: TomlSensor,
+    #[serde(default)]
+    usb: TomlUsb,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct TomlSensor {
+    device: Option<String>,
+    temp_type: Option<String>,
+    vendor_id: Option<Spanned<String>>,
+    device_id: Option<Spanned<String>>,
+    subsystem_vendor_id: Option<Spanned<String>>,
+    subsystem_device_id: Option<Spanned<String>>,
+    #[serde(rename = "match")]
+    match_mode: Option<Spanned<String>>,
+    bus: Option<Spanned<String>>,
+    pci_address: Option<Spanned<String>>,
+    hwmon_device: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct TomlUsb {
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    bus: Option<u8>,
+    port: Option<Vec<u8>>,
+    serial: Option<String>,
+    endpoint: Option<Spanned<u8>>,
+}
+
+impl TomlConfig {
+    // The top-level keys, as the display used when there's no [[display]]
+    fn defaults(&self) -> TomlDisplay {
+        TomlDisplay {
+            name: "default".to_string(),
+            units: self.units.clone(),
+            overflow: self.overflow.clone(),
+            cpu: self.cpu.clone(),
+            gpu: self.gpu.clone(),
+            usb: self.usb.clone(),
+        }
+    }
+}
+
+impl TomlDisplay {
+    fn or(&self, defaults: &TomlDisplay) -> TomlDisplay {
+        TomlDisplay {
+            name: self.name.clone(),
+            units: self.units.clone().or_else(|| defaults.units.clone()),
+            overflow: self.overflow.clone().or_else(|| defaults.overflow.clone()),
+            cpu: self.cpu.or(&defaults.cpu),
+            gpu: self.gpu.or(&defaults.gpu),
+            usb: self.usb.or(&defaults.usb),
+        }
+    }
+}
+
+impl TomlSensor {
+    fn or(&self, defaults: &TomlSensor) -> TomlSensor {
+        TomlSensor {
+            device: self.device.clone().or_else(|| defaults.device.clone()),
+            temp_type: self.temp_type.clone().or_else(|| defaults.temp_type.clone()),
+            vendor_id: self.vendor_id.clone().or_else(|| defaults.vendor_id.clone()),
+            device_id: self.device_id.clone().or_else(|| defaults.device_id.clone()),
+            subsystem_vendor_id: self.subsystem_vendor_id.clone().or_else(|| defaults.subsystem_vendor_id.clone()),
+            subsystem_device_id: self.subsystem_device_id.clone().or_else(|| defaults.subsystem_device_id.clone()),
+            match_mode: self.match_mode.clone().or_else(|| defaults.match_mode.clone()),
+            bus: self.bus.clone().or_else(|| defaults.bus.clone()),
+            pci_address: self.pci_address.clone().or_else(|| defaults.pci_address.clone()),
+            hwmon_device: self.hwmon_device.clone().or_else(|| defaults.hwmon_device.clone()),
+        }
+    }
+}
+
+impl TomlUsb {
+    fn or(&self, defaults: &TomlUsb) -> TomlUsb {
+        TomlUsb {
+            vendor_id: self.vendor_id.or(defaults.vendor_id),
+            product_id: self.product_id.or(defaults.product_id),
+            bus: self.bus.or(defaults.bus),
+            port: self.port.clone().or_else(|| defaults.port.clone()),
+            serial: self.serial.clone().or_else(|| defaults.serial.clone()),
+            endpoint: self.endpoint.clone().or_else(|| defaults.endpoint.clone()),
+        }
+    }
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn missing_key(display: &str, key: &str) -> io::Error {
+    let msg = if display == "default" {
+        format!("{} not found in config", key)
+    } else {
+        format!("{} not found in config for display [{}]", key, display)
+    };
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
+// 1-based line and column of a byte offset, for pointing at a bad value
+fn line_col(config_str: &str, offset: usize) -> (usize, usize) {
+    let before = &config_str[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+fn check_range<T>(config_str: &str, key: &str, value: &Spanned<T>, range: &RangeInclusive<T>) -> io::Result<()>
+where
+    T: PartialOrd + fmt::Display,
+{
+    if range.contains(value.get_ref()) {
+        return Ok(());
+    }
+    let (line, column) = line_col(config_str, value.span().start);
+    Err(invalid_data(format!(
+        "Invalid {} {} at line {}, column {}, expected {} to {}",
+        key, value.get_ref(), line, column, range.start(), range.end())))
+}
+
+// Lowercases the device and temp_type values, or for regexes keeps them as
+// written and compiles them
+fn check_patterns(keys: &[String; 2], values: [&String; 2], mode: MatchMode) -> io::Result<([String; 2], Option<SensorRegexes>)> {
+    if mode != MatchMode::Regex {
+        return Ok((values.map(|value| value.to_lowercase()), None));
+    }
+    let compile = |key: &str, value: &str| {
+        sensor_regex(value).map_err(|e| invalid_data(format!("Invalid {} regex '{}' in config: {}", key, value, e)))
+    };
+    let regexes = SensorRegexes {
+        device: compile(&keys[0], values[0])?,
+        temp_type: compile(&keys[1], values[1])?,
+    };
+    Ok((values.map(|value| value.to_string()), Some(regexes)))
+}
+
+// Parses an optional value such as units, pointing at it if it's invalid
+fn choice<T>(
+    config_str: &str,
+    key: &str,
+    value: Option<&Spanned<String>>,
+    parse: fn(&str) -> Option<T>,
+    expected: &str,
+) -> io::Result<Option<T>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let parsed = parse(value.get_ref()).ok_or_else(|| {
+        let (line, column) = line_col(config_str, value.span().start);
+        invalid_data(format!(
+            "Invalid {} '{}' at line {}, column {}, expected {}",
+            key, value.get_ref(), line, column, expected))
+    })?;
+    Ok(Some(parsed))
+}
+
+const BUS_NAMES: &str = "pci, isa, platform, acpi, hid, i2c, spi or virtual";
+
+fn parse_bus(s: &str) -> Option<Bus> {
+    let s = s.trim().to_lowercase();
+    Bus::ALL.into_iter().find(|bus| bus.to_string() == s)
+}
+
+const UNITS_NAMES: &str = "celsius or fahrenheit";
+
+// Keywords are case-insensitive in both formats, "Fahrenheit" as well as
+// "fahrenheit"
+fn parse_units(s: &str) -> Option<Units> {
+    match s.trim().to_lowercase().as_str() {
+        "celsius" => Some(Units::Celsius),
+        "fahrenheit" => Some(Units::Fahrenheit),
+        _ => None,
+    }
+}
+
+const MATCH_NAMES: &str = "prefix, exact or regex";
+
+fn parse_match_mode(s: &str) -> Option<MatchMode> {
+    match s.trim().to_lowercase().as_str() {
+        "prefix" => Some(MatchMode::Prefix),
+        "exact" => Some(MatchMode::Exact),
+        "regex" => Some(MatchMode::Regex),
+        _ => None,
+    }
+}
+
+const OVERFLOW_NAMES: &str = "clamp or blank";
+
+fn parse_overflow(s: &str) -> Option<Overflow> {
+    match s.trim().to_lowercase().as_str() {
+        "clamp" => Some(Overflow::Clamp),
+        "blank" => Some(Overflow::Blank),
+        _ => None,
+    }
+}
+
+const PCI_ADDRESS_EXPECTED: &str = "a PCI address, e.g. 0000:03:00.0";
+
+// Parses a PCI address as shown by lspci, with or without the domain
+// ("03:00.0" is "0000:03:00.0")
+fn parse_pci_address(s: &str) -> Option<String> {
+    let s = s.trim().to_lowercase();
+    let address = if s.matches(':').count() == 1 { format!("0000:{}", s) } else { s };
+    listing::is_pci_address(&address).then_some(address)
+}
+
+const DEVICE_PATH_EXPECTED: &str = "an absolute path, e.g. /sys/devices/...";
+
+fn parse_device_path(s: &str) -> Option<PathBuf> {
+    Some(PathBuf::from(s.trim())).filter(|path| path.is_absolute())
+}
+
+const HEX_ID_EXPECTED: &str = "a hex ID, e.g. 1022";
+
+// Parses a PCI or USB vendor/product ID, given as hex with or without 0x
+pub fn parse_hex_id(s: &str) -> Option<u16> {
+    let s = s.trim().to_lowercase();
+    let s = s.trim_start_matches("0x");
+    // from_str_radix would take a sign, and the kernel never pads further
+    if s.is_empty() || s.len() > 4 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u16::from_str_radix(s, 16).ok()
+}
+
+// Parses a USB port chain such as "1.4"
+fn parse_port(s: &str) -> Option<Vec<u8>> {
+    s.trim().split('.').map(|p| p.parse().ok()).collect()
+}
+
+// Parses a USB endpoint address, given as hex (0x03) or decimal (3)
+fn parse_endpoint(s: &str) -> Option<u8> {
+    let s = s.trim().to_lowercase();
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempDir;
+    use crate::usb;
+
+    const SENSORS: &str = "cpu_device=k10temp\ncpu_temp_type=tctl\ngpu_device=amdgpu\ngpu_temp_type=edge\n";
+
+    #[test]
+    fn test_parse_single_display() {
+        let config = AppConfig::parse_legacy(&format!("{}update_interval=500\n", SENSORS)).unwrap();
+
+        assert_eq!(500, config.update_interval);
+        assert_eq!(1, config.displays.len());
+        assert_eq!("k10temp", config.displays[0].cpu_device);
+        assert_eq!(UsbTarget::default(), config.displays[0].usb);
+    }
+
+    #[test]
+    fn test_parse_multiple_displays() {
+        let config = AppConfig::parse_legacy(&format!("{}
+[top]
+usb_serial=A1
+usb_vendor_id=0x2022
+usb_product_id=0523
+
+[front]
+units=Fahrenheit
+overflow=blank
+usb_bus=3
+usb_port=1.4
+gpu_device=nouveau
+gpu_temp_type=temp1
+endpoint=0x02
+", SENSORS)).unwrap();
+
+        let [top, front] = &config.displays[..] else {
+            panic!("expected two displays, got {:?}", config.displays);
+        };
+        assert_eq!("top", top.name);
+        assert_eq!("amdgpu", top.gpu_device);
+        assert_eq!(Some("A1".to_string()), top.usb.serial);
+        assert_eq!((usb::VENDOR_ID, 0x0523), (top.usb.vendor_id, top.usb.product_id));
+
+        assert_eq!("front", front.name);
+        assert_eq!("nouveau", front.gpu_device);
+        assert_eq!("k10temp", front.cpu_device);
+        assert_eq!(Some(3), front.usb.bus);
+        assert_eq!(Some(vec![1, 4]), front.usb.port);
+        assert_eq!(Some(0x02), front.usb.endpoint);
+        assert_eq!(Units::Celsius, top.units);
+        assert_eq!(Units::Fahrenheit, front.units);
+        assert_eq!(Overflow::Clamp, top.overflow);
+        assert_eq!(Overflow::Blank, front.overflow);
+        assert_eq!(None, front.usb.serial);
+    }
+
+    #[test]
+    fn test_parse_missing_key_names_display() {
+        let err = AppConfig::parse_legacy("[top]\ncpu_device=k10temp\n").unwrap_err();
+        assert_eq!("cpu_temp_type not found in config for display [top]", err.to_string());
+    }
+
+    #[test]
+    fn test_parse_legacy_rejects_bad_values_and_warns_on_unknown_keys() {
+        let config = AppConfig::parse_legacy(&format!("{}cpu_devcie=coretemp\n", SENSORS)).unwrap();
+        assert_eq!(vec!["Unknown key 'cpu_devcie' at line 5 in config, ignoring it"], config.warnings);
+
+        let err = AppConfig::parse_legacy(&format!("{}update_interval=1s\n", SENSORS)).unwrap_err();
+        assert_eq!("Invalid update_interval '1s' in config, expected 100 to 3600000 milliseconds", err.to_string());
+        assert!(AppConfig::parse_legacy(&format!("{}update_interval=0\n", SENSORS)).is_err());
+        assert!(AppConfig::parse_legacy(&format!("{}endpoint=0x81\n", SENSORS)).is_err());
+    }
+
+    const TOML_SENSORS: &str = r#"
+[cpu]
+device = "k10temp"
+temp_type = "Tctl"
+vendor_id = "0x1022"
+
+[gpu]
+device = "amdgpu"
+temp_type = "edge"
+"#;
+
+    #[test]
+    fn test_parse_toml_single_display() {
+        let config = AppConfig::parse_toml(&format!("update_interval = 500\n{}", TOML_SENSORS)).unwrap();
+
+        assert_eq!(500, config.update_interval);
+        assert!(config.warnings.is_empty());
+        let [display] = &config.displays[..] else {
+            panic!("expected one display, got {:?}", config.displays);
+        };
+        assert_eq!("default", display.name);
+        assert_eq!("tctl", display.cpu_temp_type);
+        assert_eq!(Some(0x1022), display.cpu_ids.vendor);
+        assert_eq!(PciIds::default(), display.gpu_ids);
+        assert_eq!(UsbTarget::default(), display.usb);
+    }
+
+    #[test]
+    fn test_keywords_ignore_case_in_both_formats() {
+        let toml = AppConfig::parse_toml(&format!("units = \"Fahrenheit\"\noverflow = \"BLANK\"\n{}", TOML_SENSORS)).unwrap();
+        let legacy = AppConfig::parse_legacy(&format!("{}units=Fahrenheit\noverflow=BLANK\n", SENSORS)).unwrap();
+
+        for config in [toml, legacy] {
+            assert_eq!(Units::Fahrenheit, config.displays[0].units);
+            assert_eq!(Overflow::Blank, config.displays[0].overflow);
+        }
+    }
+
+    #[test]
+    fn test_rejects_displays_for_the_same_device() {
+        let err = AppConfig::parse_legacy(&format!("{}[top]\nusb_serial=A1\n[front]\nusb_bus=3\n", SENSORS)).unwrap_err();
+        assert_eq!("Displays [top] and [front] can both select the same display 2022:0522 serial A1, set a different usb bus, port or serial for each", err.to_string());
+
+        let err = AppConfig::parse_toml(&format!("{}[[display]]\nname = \"top\"\n[[display]]\nname = \"front\"\n", TOML_SENSORS)).unwrap_err();
+        assert!(err.to_string().starts_with("Displays [top] and [front] can both select"), "{}", err);
+
+        // A selector set differently on both, or other IDs, tell them apart
+        assert!(AppConfig::parse_legacy(&format!("{}[top]\nusb_bus=1\n[front]\nusb_bus=3\nusb_serial=B2\n", SENSORS)).is_ok());
+        assert!(AppConfig::parse_legacy(&format!("{}[top]\n[front]\nusb_product_id=0523\n", SENSORS)).is_ok());
+    }
+
+    #[test]
+    fn test_parse_toml_multiple_displays() {
+        let config = AppConfig::parse_toml(&format!(r#"units = "fahrenheit"
+{}
+[[display]]
+name = "top"
+usb = {{ serial = "A1", product_id = 0x0523 }}
+
+[[display]]
+name = "front"
+units = "celsius"
+overflow = "blank"
+gpu = {{ device = "nouveau", temp_type = "temp1" }}
+usb = {{ bus = 3, port = [1, 4], endpoint = 0x02 }}
+"#, TOML_SENSORS)).unwrap();
+
+        let [top, front] = &config.displays[..] else {
+            panic!("expected two displays, got {:?}", config.displays);
+        };
+        assert_eq!(1000, config.update_interval);
+        assert_eq!("top", top.name);
+        assert_eq!("amdgpu", top.gpu_device);
+        assert_eq!(Units::Fahrenheit, top.units);
+        assert_eq!(Some("A1".to_string()), top.usb.serial);
+        assert_eq!((usb::VENDOR_ID, 0x0523), (top.usb.vendor_id, top.usb.product_id));
+
+        assert_eq!("front", front.name);
+        assert_eq!("nouveau", front.gpu_device);
+        assert_eq!("k10temp", front.cpu_device);
+        assert_eq!(Units::Celsius, front.units);
+        assert_eq!(Overflow::Blank, front.overflow);
+        assert_eq!(Some(3), front.usb.bus);
+        assert_eq!(Some(vec![1, 4]), front.usb.port);
+        assert_eq!(Some(0x02), front.usb.endpoint);
+    }
+
+    #[test]
+    fn test_parse_toml_warns_on_unknown_keys() {
+        let config = AppConfig::parse_toml(&format!("intervall = 500\n{}\n[[display]]\nname = \"top\"\nusb.seria = \"A1\"\n", TOML_SENSORS)).unwrap();
+
+        assert_eq!(vec![
+            "Unknown key 'display.0.usb.seria' in config, ignoring it",
+            "Unknown key 'intervall' in config, ignoring it",
+        ], config.warnings);
+    }
+
+    #[test]
+    fn test_parse_toml_errors_point_at_the_value() {
+        let err = AppConfig::parse_toml(&format!("update_interval = 10\n{}", TOML_SENSORS)).unwrap_err();
+        assert_eq!("Invalid update_interval 10 at line 1, column 19, expected 100 to 3600000", err.to_string());
+
+        let err = AppConfig::parse_toml(&format!("{}[usb]\nendpoint = 0x81\n", TOML_SENSORS)).unwrap_err();
+        assert_eq!("Invalid usb.endpoint 129 at line 11, column 12, expected 1 to 15", err.to_string());
+
+        let err = AppConfig::parse_toml(&TOML_SENSORS.replace("0x1022", "AMD")).unwrap_err();
+        assert_eq!("Invalid cpu.vendor_id 'AMD' at line 5, column 13, expected a hex ID, e.g. 1022", err.to_string());
+
+        let err = AppConfig::parse_toml(&format!("units = \"kelvin\"\n{}", TOML_SENSORS)).unwrap_err();
+        assert_eq!("Invalid units 'kelvin' at line 1, column 9, expected celsius or fahrenheit", err.to_string());
+
+        let err = AppConfig::parse_toml("[cpu]\ndevice = k10temp\n").unwrap_err();
+        assert!(err.to_string().contains("line 2, column 10"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_toml_missing_key_names_display() {
+        let err = AppConfig::parse_toml("[[display]]\nname = \"top\"\ncpu.device = \"k10temp\"\n").unwrap_err();
+        assert_eq!("cpu.temp_type not found in config for display [top]", err.to_string());
+    }
+
+    #[test]
+    fn test_parse_match_modes() {
+        let config = AppConfig::parse_toml(&format!("{}\n[[display]]\nname = \"top\"\ncpu = {{ device = \"k10temp|zenpower\", temp_type = \"Tccd\\\\d\", match = \"regex\" }}\ngpu.match = \"Exact\"\n", TOML_SENSORS)).unwrap();
+        let display = &config.displays[0];
+        assert_eq!((MatchMode::Regex, r"Tccd\d"), (display.cpu_match, display.cpu_temp_type.as_str()));
+        assert!(display.cpu_regexes.as_ref().is_some_and(|regexes| regexes.temp_type.is_match("TCCD3")));
+        assert_eq!((MatchMode::Exact, "amdgpu"), (display.gpu_match, display.gpu_device.as_str()));
+        assert!(display.gpu_regexes.is_none());
+
+        let config = AppConfig::parse_legacy(&format!("{}cpu_match=Exact\n", SENSORS)).unwrap();
+        assert_eq!((MatchMode::Exact, MatchMode::Prefix), (config.displays[0].cpu_match, config.displays[0].gpu_match));
+
+        let err = AppConfig::parse_legacy(&format!("{}gpu_match=fuzzy\n", SENSORS)).unwrap_err();
+        assert_eq!("Invalid gpu_match 'fuzzy' in config, expected prefix, exact or regex", err.to_string());
+        let err = AppConfig::parse_toml(&TOML_SENSORS.replace("[gpu]\n", "[gpu]\nmatch = \"fuzzy\"\n")).unwrap_err();
+        assert_eq!("Invalid gpu.match 'fuzzy' at line 8, column 9, expected prefix, exact or regex", err.to_string());
+        let err = AppConfig::parse_toml("[cpu]\ndevice = \"k10temp\"\ntemp_type = \"tccd(\"\nmatch = \"regex\"\n").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid cpu.temp_type regex 'tccd(' in config"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_device_pins() {
+        let config = AppConfig::parse_toml(&format!("{}pci_address = \"03:00.0\"\n[[display]]\nname = \"top\"\ncpu.hwmon_device = \"/sys/devices/pci0000:00/0000:00:18.3\"\n", TOML_SENSORS)).unwrap();
+        let display = &config.displays[0];
+        assert_eq!(Some("0000:03:00.0"), display.gpu_pci_address.as_deref());
+        assert_eq!(Some(Path::new("/sys/devices/pci0000:00/0000:00:18.3")), display.cpu_hwmon_device.as_deref());
+        assert_eq!((None, None), (display.cpu_pci_address.as_deref(), display.gpu_hwmon_device.as_deref()));
+        assert_eq!("amdgpu (type: edge) [PCI: 0000:03:00.0]", display.format_device_info(DeviceType::Gpu));
+
+        let config = AppConfig::parse_legacy(&format!("{}gpu_pci_address=0000:0A:00.0\n", SENSORS)).unwrap();
+        assert_eq!(Some("0000:0a:00.0"), config.displays[0].gpu_pci_address.as_deref());
+
+        let err = AppConfig::parse_toml(&format!("{}pci_address = \"3:0.0\"\n", TOML_SENSORS)).unwrap_err();
+        assert_eq!("Invalid gpu.pci_address '3:0.0' at line 10, column 15, expected a PCI address, e.g. 0000:03:00.0", err.to_string());
+        let config = AppConfig::parse_toml(&format!("{}bus = \"Platform\"\n", TOML_SENSORS)).unwrap();
+        assert_eq!((None, Some(Bus::Platform)), (config.displays[0].cpu_bus, config.displays[0].gpu_bus));
+        assert_eq!("amdgpu (type: edge) [bus: platform]", config.displays[0].format_device_info(DeviceType::Gpu));
+        let config = AppConfig::parse_legacy(&format!("{}cpu_bus=hid\n", SENSORS)).unwrap();
+        assert_eq!(Some(Bus::Hid), config.displays[0].cpu_bus);
+        let err = AppConfig::parse_toml(&format!("{}bus = \"usb\"\n", TOML_SENSORS)).unwrap_err();
+        assert_eq!("Invalid gpu.bus 'usb' at line 10, column 7, expected pci, isa, platform, acpi, hid, i2c, spi or virtual", err.to_string());
+        let err = AppConfig::parse_legacy(&format!("{}cpu_hwmon_device=hwmon2\n", SENSORS)).unwrap_err();
+        assert_eq!("Invalid cpu_hwmon_device 'hwmon2' in config, expected an absolute path, e.g. /sys/devices/...", err.to_string());
+    }
+
+    #[test]
+    fn test_parse_pci_ids() {
+        let config = AppConfig::parse_toml(&format!("{}subsystem_vendor_id = \"1DA2\"\nsubsystem_device_id = \"0x475e\"\n", TOML_SENSORS)).unwrap();
+        let display = &config.displays[0];
+        assert_eq!(PciIds { vendor: Some(0x1022), ..Default::default() }, display.cpu_ids);
+        assert_eq!(PciIds { subsystem_vendor: Some(0x1da2), subsystem_device: Some(0x475e), ..Default::default() }, display.gpu_ids);
+        assert_eq!("k10temp (type: tctl) [ID: 1022:*]", display.format_device_info(DeviceType::Cpu));
+        assert_eq!("amdgpu (type: edge) [ID: *:*] [subsystem: 1da2:475e]", display.format_device_info(DeviceType::Gpu));
+
+        let config = AppConfig::parse_legacy(&format!("{}gpu_vendor_id=0x1002\ngpu_subsystem_vendor_id=1da2\n", SENSORS)).unwrap();
+        assert_eq!(PciIds { vendor: Some(0x1002), subsystem_vendor: Some(0x1da2), ..Default::default() }, config.displays[0].gpu_ids);
+        let config = AppConfig::parse_legacy(&format!("{}cpu_vendor_id=\ngpu_device_id= \n", SENSORS)).unwrap();
+        assert_eq!((PciIds::default(), PciIds::default()), (config.displays[0].cpu_ids, config.displays[0].gpu_ids));
+
+        let config = AppConfig::parse_toml(&format!("{}device_id = \"\"\nsubsystem_vendor_id = \" \"\n", TOML_SENSORS)).unwrap();
+        assert_eq!(PciIds::default(), config.displays[0].gpu_ids);
+
+        // IDs are whole 16-bit numbers, not text to look for
+        for id in ["0x", "10022", "-10", "+10"] {
+            assert!(AppConfig::parse_toml(&format!("{}device_id = \"{}\"\n", TOML_SENSORS, id)).is_err(), "{:?}", id);
+        }
+        let err = AppConfig::parse_legacy(&format!("{}cpu_subsystem_device_id=g123\n", SENSORS)).unwrap_err();
+        assert_eq!("Invalid cpu_subsystem_device_id 'g123' in config, expected a hex ID, e.g. 1022", err.to_string());
+    }
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        |key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| OsString::from(v))
+    }
+
+    #[test]
+    fn test_search_paths() {
+        let system = [Path::new(CONFIG_DIR).join("config.toml"), Path::new(CONFIG_DIR).join("config.conf")];
+
+        assert_eq!([
+            PathBuf::from("/xdg/antec-flux-pro-display/config.toml"),
+            PathBuf::from("/xdg/antec-flux-pro-display/config.conf"),
+        ].iter().chain(&system).collect::<Vec<_>>(),
+            search_paths(env(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/me")])).iter().collect::<Vec<_>>());
+
+        // Relative XDG_CONFIG_HOME values are invalid and ignored
+        assert_eq!(PathBuf::from("/home/me/.config/antec-flux-pro-display/config.toml"),
+            search_paths(env(&[("XDG_CONFIG_HOME", "xdg"), ("HOME", "/home/me")]))[0]);
+
+        assert_eq!(system.to_vec(), search_paths(env(&[])));
+    }
+
+    #[test]
+    fn test_find_config() {
+        let dir = TempDir::new("find-config");
+        let toml_path = dir.path().join("antec-flux-pro-display/config.toml");
+        let legacy_path = dir.write("antec-flux-pro-display/config.conf", SENSORS);
+        let xdg = dir.path().to_str().unwrap();
+
+        // The legacy file is still found, and config.toml takes precedence.
+        assert_eq!(legacy_path, find_config(None, env(&[("XDG_CONFIG_HOME", xdg)])).unwrap());
+        fs::write(&toml_path, TOML_SENSORS).unwrap();
+        assert_eq!(toml_path, find_config(None, env(&[("XDG_CONFIG_HOME", xdg)])).unwrap());
+
+        // An explicit path wins over the search, and --config over the environment.
+        let vars = [("XDG_CONFIG_HOME", xdg), (CONFIG_ENV, legacy_path.to_str().unwrap())];
+        let from_env = env(&vars);
+        assert_eq!(legacy_path, find_config(None, &from_env).unwrap());
+        assert_eq!(toml_path, find_config(Some(&toml_path), &from_env).unwrap());
+        let missing = dir.path().join("missing.toml");
+        assert_eq!(io::ErrorKind::NotFound, find_config(Some(&missing), &from_env).unwrap_err().kind());
+
+        // Both formats load, picked by the extension.
+        assert_eq!("k10temp", AppConfig::load(&legacy_path).unwrap().displays[0].cpu_device);
+        assert_eq!("k10temp", AppConfig::load(&toml_path).unwrap().displays[0].cpu_device);
+    }
+
+    #[test]
+    fn test_units_convert() {
+        assert_eq!(45.5, Units::Celsius.convert(45.5));
+        assert_eq!(32.0, Units::Fahrenheit.convert(0.0));
+        assert_eq!(98.6, Units::Fahrenheit.convert(37.0));
+        assert_eq!(-40.0, Units::Fahrenheit.convert(-40.0));
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(Some(0x03), parse_endpoint("0x03"));
+        assert_eq!(Some(0x02), parse_endpoint("0X2"));
+        assert_eq!(Some(3), parse_endpoint("3"));
+        assert_eq!(None, parse_endpoint("0x100"));
+        assert_eq!(None, parse_endpoint("out"));
+    }
+}
@@ -0,0 +1,264 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::get_time_string;
+use crate::hotplug::HotplugEvent;
+use crate::protocol::Temperatures;
+use crate::transport::DisplayTransport;
+use crate::usb::{UsbError, UsbTarget};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+pub const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps a display transport open, reopening it with exponential backoff
+/// whenever the device is lost (cable reseated, hub reset, ...).
+pub struct Connection<T: DisplayTransport> {
+    target: UsbTarget,
+    device: Option<T>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl<T: DisplayTransport> Connection<T> {
+    pub fn new(target: UsbTarget) -> Self {
+        Self {
+            target,
+            device: None,
+            backoff: INITIAL_BACKOFF,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// Opens and claims the device, replacing any existing handle.
+    pub fn connect(&mut self) -> Result<(), UsbError> {
+        self.device = None;
+        let mut device = T::open(&self.target)?;
+        device.claim_interface()?;
+        self.device = Some(device);
+        self.backoff = INITIAL_BACKOFF;
+        Ok(())
+    }
+
+    /// Keeps trying to connect every `poll_interval` until the device shows
+    /// up, or until `timeout` has passed. Waits forever if `timeout` is `None`.
+    pub fn wait_for_device(&mut self, timeout: Option<Duration>, poll_interval: Duration) -> Result<(), UsbError> {
+        let start = Instant::now();
+        let mut announced = false;
+
+        loop {
+            let e = match self.connect() {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+            if timeout.is_some_and(|timeout| start.elapsed() + poll_interval > timeout) {
+                return Err(e);
+            }
+
+            if !announced {
+                info!("[{}] Waiting for display {} to appear...",
+                         get_time_string(), self.target);
+                announced = true;
+            }
+
+            thread::sleep(poll_interval);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_connected(&self) -> bool {
+        self.device.is_some()
+    }
+
+    #[cfg(test)]
+    pub fn device(&self) -> Option<&T> {
+        self.device.as_ref()
+    }
+
+    /// Claims the interface again, e.g. after the system resumed from suspend.
+    pub fn reclaim(&mut self, now: Instant) {
+        let Some(device) = &mut self.device else {
+            return;
+        };
+        if let Err(e) = device.claim_interface() {
+            self.disconnect(e, now);
+        }
+    }
+
+    /// Applies an attach/detach event, so an unplugged display is dropped
+    /// right away and a returning one is reopened without waiting for the
+    /// backoff. Events for other devices are ignored.
+    pub fn handle_hotplug_event(&mut self, event: HotplugEvent, now: Instant) {
+        match event {
+            HotplugEvent::Left(location) => {
+                let Some(device) = &self.device else {
+                    return;
+                };
+                if device.location().is_some_and(|l| l != location) {
+                    return;
+                }
+                info!("[{}] Display {} unplugged.", get_time_string(), self.target);
+                self.device = None;
+                self.backoff = INITIAL_BACKOFF;
+                self.schedule_retry(now);
+            }
+            HotplugEvent::Arrived(_) => {
+                // Whether it's actually ours is only known once it's opened
+                if self.device.is_none() {
+                    self.backoff = INITIAL_BACKOFF;
+                    self.next_attempt = now;
+                }
+            }
+        }
+    }
+
+    /// Sends the temperatures to the display. If the device was lost, a
+    /// reconnect is attempted first once the current backoff has elapsed;
+    /// until then the frame is dropped.
+    pub fn send_payload(&mut self, temperatures: &Temperatures, now: Instant) {
+        if self.device.is_none() {
+            if now < self.next_attempt {
+                return;
+            }
+
+            match self.connect() {
+                Ok(()) => info!("[{}] Display {} reconnected.", get_time_string(), self.target),
+                Err(e) => {
+                    self.schedule_retry(now);
+                    eprintln!("[{}] Reconnecting display {} failed: {}. Retrying in {}s",
+                              get_time_string(), self.target, e, self.next_attempt.duration_since(now).as_secs());
+                    return;
+                }
+            }
+        }
+
+        let Some(device) = &mut self.device else {
+            return;
+        };
+
+        match device.send_payload(temperatures) {
+            Ok(()) => (),
+            Err(UsbError::WriteTimeout) => {
+                eprintln!("[{}] {}", get_time_string(), UsbError::WriteTimeout);
+            }
+            Err(e) => self.disconnect(e, now),
+        }
+    }
+
+    fn disconnect(&mut self, e: UsbError, now: Instant) {
+        self.device = None;
+        self.backoff = INITIAL_BACKOFF;
+        self.schedule_retry(now);
+        eprintln!("[{}] Display {} lost: {}. Reconnecting in {}s",
+                  get_time_string(), self.target, e, self.next_attempt.duration_since(now).as_secs());
+    }
+
+    fn schedule_retry(&mut self, now: Instant) {
+        self.next_attempt = now + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol::Reading;
+    use crate::transport::mock::{self, MockTransport};
+    use crate::usb;
+
+    const TEMPERATURES: Temperatures = Temperatures {
+        cpu: Reading::Digits([2, 4, 0]),
+        gpu: Reading::Missing,
+    };
+
+    fn connected() -> Connection<MockTransport> {
+        let mut display = Connection::new(UsbTarget::default());
+        display.connect().unwrap();
+        display
+    }
+
+    #[test]
+    fn test_wait_for_device_connects_once_present() {
+        let mut display = Connection::<MockTransport>::new(UsbTarget::default());
+        mock::fail_next_open(UsbError::NotFound { vendor_id: usb::VENDOR_ID, product_id: usb::PRODUCT_ID });
+        mock::fail_next_open(UsbError::PermissionDenied { vendor_id: usb::VENDOR_ID, product_id: usb::PRODUCT_ID });
+
+        display.wait_for_device(None, Duration::from_millis(1)).unwrap();
+
+        assert!(display.is_connected());
+    }
+
+    #[test]
+    fn test_wait_for_device_times_out() {
+        let mut display = Connection::<MockTransport>::new(UsbTarget::default());
+        let not_found = UsbError::NotFound { vendor_id: usb::VENDOR_ID, product_id: usb::PRODUCT_ID };
+        for _ in 0..10 {
+            mock::fail_next_open(not_found);
+        }
+
+        let result = display.wait_for_device(Some(Duration::from_millis(3)), Duration::from_millis(1));
+
+        assert_eq!(Err(not_found), result);
+        assert!(!display.is_connected());
+    }
+
+    #[test]
+    fn test_hotplug_events() {
+        let mut display = connected();
+        let mut other = connected();
+        let now = Instant::now();
+        let location = display.device().unwrap().location().unwrap();
+
+        // Unplugging drops only that device, without waiting for a failed write.
+        display.handle_hotplug_event(HotplugEvent::Left(location), now);
+        other.handle_hotplug_event(HotplugEvent::Left(location), now);
+        assert!(!display.is_connected());
+        assert!(other.is_connected());
+
+        // Plugging back in reconnects on the next frame, ignoring the backoff.
+        display.handle_hotplug_event(HotplugEvent::Arrived(location), now);
+        display.send_payload(&TEMPERATURES, now);
+        assert_eq!(1, display.device().unwrap().frames.len());
+    }
+
+    #[test]
+    fn test_write_timeout_keeps_device() {
+        let mut display = connected();
+        let now = Instant::now();
+        display.device.as_mut().unwrap().send_errors.push_back(UsbError::WriteTimeout);
+
+        display.send_payload(&TEMPERATURES, now);
+        display.send_payload(&TEMPERATURES, now);
+
+        assert_eq!(1, display.device().unwrap().frames.len());
+    }
+
+    #[test]
+    fn test_reconnects_with_exponential_backoff() {
+        let mut display = connected();
+        let start = Instant::now();
+        let secs = |s| start + Duration::from_secs(s);
+        display.device.as_mut().unwrap().send_errors.push_back(UsbError::Disconnected);
+
+        // Unplugged: the device is dropped and the first retry is 1s away.
+        display.send_payload(&TEMPERATURES, start);
+        assert!(!display.is_connected());
+
+        // Still absent at 1s and 3s, pushing the next attempts to 3s and 7s.
+        mock::fail_next_open(UsbError::NotFound { vendor_id: usb::VENDOR_ID, product_id: usb::PRODUCT_ID });
+        display.send_payload(&TEMPERATURES, secs(1));
+        mock::fail_next_open(UsbError::NotFound { vendor_id: usb::VENDOR_ID, product_id: usb::PRODUCT_ID });
+        display.send_payload(&TEMPERATURES, secs(3));
+
+        // No attempt is made before the backoff elapses.
+        display.send_payload(&TEMPERATURES, secs(6));
+        assert!(!display.is_connected());
+
+        // Plugged back in: frames resume on the new handle.
+        display.send_payload(&TEMPERATURES, secs(7));
+        let device = display.device().unwrap();
+        assert_eq!(1, device.claims);
+        assert_eq!(1, device.frames.len());
+    }
+}
@@ -0,0 +1,627 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::config::{self, DeviceType, DisplayConfig, MatchMode, PciIds, SensorRegexes};
+use crate::listing::{self, Bus, ChipInfo};
+use crate::source::{self, SensorRef, SensorSource};
+
+/// The sensor one role (CPU or GPU) of a display is configured to show.
+pub struct SensorSpec<'a> {
+    pub device_type: DeviceType,
+    pub device: &'a str,
+    pub temp_type: &'a str,
+    pub pci_ids: PciIds,
+    pub mode: MatchMode,
+    pub bus: Option<Bus>,
+    pub pci_address: Option<&'a str>,
+    pub hwmon_device: Option<&'a Path>,
+    // Compiled `device` and `temp_type` for MatchMode::Regex
+    regexes: Option<SensorRegexes>,
+}
+
+impl<'a> SensorSpec<'a> {
+    pub fn of(config: &'a DisplayConfig, device_type: DeviceType) -> Self {
+        let (device, temp_type, pci_ids, mode, regexes) = match device_type {
+            DeviceType::Cpu => (&config.cpu_device, &config.cpu_temp_type, config.cpu_ids, config.cpu_match, &config.cpu_regexes),
+            DeviceType::Gpu => (&config.gpu_device, &config.gpu_temp_type, config.gpu_ids, config.gpu_match, &config.gpu_regexes),
+        };
+        let (bus, pci_address, hwmon_device) = match device_type {
+            DeviceType::Cpu => (config.cpu_bus, &config.cpu_pci_address, &config.cpu_hwmon_device),
+            DeviceType::Gpu => (config.gpu_bus, &config.gpu_pci_address, &config.gpu_hwmon_device),
+        };
+        SensorSpec {
+            bus,
+            pci_address: pci_address.as_deref(),
+            hwmon_device: hwmon_device.as_deref(),
+            pci_ids,
+            ..SensorSpec::new(device_type, device, temp_type, mode, regexes.clone())
+        }
+    }
+
+    fn new(device_type: DeviceType, device: &'a str, temp_type: &'a str, mode: MatchMode, regexes: Option<SensorRegexes>) -> Self {
+        SensorSpec {
+            device_type, device, temp_type, mode,
+            pci_ids: PciIds::default(), bus: None, pci_address: None, hwmon_device: None, regexes,
+        }
+    }
+
+    // Whether `value` is selected by `pattern`, the configured device or
+    // temp_type, with `regex` compiled from it. Without a compiled regex
+    // nothing matches in the regex mode.
+    fn matches(&self, pattern: &str, regex: Option<&Regex>, value: &str) -> bool {
+        match (self.mode, regex) {
+            (MatchMode::Prefix, _) => value.to_lowercase().starts_with(pattern),
+            (MatchMode::Exact, _) => value.to_lowercase() == pattern,
+            (MatchMode::Regex, Some(regex)) => regex.is_match(value),
+            (MatchMode::Regex, None) => false,
+        }
+    }
+
+    // Checks the chip's bus, IDs, PCI address and device, where configured.
+    // These pin a role to one physical device, whatever its hwmon number.
+    fn check_chip(&self, chip: &ChipInfo, sysfs_root: &Path) -> Result<(), Mismatch> {
+        if self.bus.is_some_and(|bus| !on_bus(chip.bus, bus)) {
+            return Err(Mismatch::Bus);
+        }
+        if !self.pci_ids.is_empty() && !matches_ids(sysfs_root, chip, &self.pci_ids) {
+            return Err(Mismatch::Id);
+        }
+        if self.pci_address.is_some() && chip.pci_address.as_deref() != self.pci_address {
+            return Err(Mismatch::PciAddress);
+        }
+        if self.hwmon_device.is_some_and(|device| !is_device(&chip.path, &rebase(sysfs_root, device))) {
+            return Err(Mismatch::Device);
+        }
+        Ok(())
+    }
+
+    // Checks one feature, given the chip's full name (e.g.
+    // "k10temp-pci-00c3"), its prefix ("k10temp") and the result of
+    // check_chip
+    fn check(&self, chip_name: &str, chip_prefix: &str, label: &str, chip: Result<(), Mismatch>) -> Result<Fit, Mismatch> {
+        let (device_regex, label_regex) = match &self.regexes {
+            Some(regexes) => (Some(&regexes.device), Some(&regexes.temp_type)),
+            None => (None, None),
+        };
+        if ![chip_prefix, chip_name, label].iter().any(|name| self.matches(self.device, device_regex, name)) {
+            return Err(Mismatch::Name);
+        }
+        if !self.matches(self.temp_type, label_regex, label) {
+            return Err(Mismatch::Label);
+        }
+        chip?;
+        if self.mode == MatchMode::Prefix && label.to_lowercase() != self.temp_type {
+            return Ok(Fit::Prefix);
+        }
+        Ok(Fit::Exact)
+    }
+}
+
+/// How well a matching sensor's label fits `temp_type`. Only the prefix
+/// mode has prefix fits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// The label is `temp_type`, or matches it in the exact and regex modes
+    Exact,
+    /// The label only starts with `temp_type`, e.g. "Tccd10" for "tccd1"
+    Prefix,
+}
+
+/// Why a sensor was not used for a role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Neither the chip name nor the label matches `device`
+    Name,
+    /// The label doesn't match `temp_type`
+    Label,
+    /// The chip isn't on the configured bus
+    Bus,
+    /// The chip's vendor, device or subsystem ID differs from the configured
+    /// one
+    Id,
+    /// The chip is on another PCI address than `pci_address`
+    PciAddress,
+    /// The chip doesn't belong to `hwmon_device`
+    Device,
+    /// The sensor has no temperature input to read
+    NoInput,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Name => write!(f, "name mismatch"),
+            Mismatch::Label => write!(f, "label mismatch"),
+            Mismatch::Bus => write!(f, "bus mismatch"),
+            Mismatch::Id => write!(f, "ID mismatch"),
+            Mismatch::PciAddress => write!(f, "PCI address mismatch"),
+            Mismatch::Device => write!(f, "device path mismatch"),
+            Mismatch::NoInput => write!(f, "no input subfeature"),
+        }
+    }
+}
+
+/// A sensor that matched a role.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matched {
+    /// Name of the input subfeature, e.g. "temp1_input"
+    pub input: String,
+    pub value: Option<f64>,
+    pub fit: Fit,
+}
+
+/// One sensor considered for a role, and the outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub chip: String,
+    pub feature: String,
+    pub label: String,
+    pub outcome: Result<Matched, Mismatch>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({})", self.chip, self.feature, self.label)
+    }
+}
+
+/// Why no sensor could be picked for a role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unresolved {
+    NotFound,
+    /// Indices of the equally good matches
+    Ambiguous(Vec<usize>),
+}
+
+// Helper function to find sensors
+pub fn discover_features(sensors: &dyn SensorSource, config: &DisplayConfig) -> (Option<SensorRef>, Option<SensorRef>) {
+    let specs = [SensorSpec::of(config, DeviceType::Cpu), SensorSpec::of(config, DeviceType::Gpu)];
+    let [cpu, gpu] = specs.map(|spec| {
+        // An empty device leaves the role unused, e.g. without a known GPU
+        if spec.device.is_empty() {
+            return None;
+        }
+        let mut found = walk(&sensors.chips(), sensors.sysfs_root(), &spec);
+        let candidates: Vec<Candidate> = found.iter().map(|(candidate, _)| candidate.clone()).collect();
+        let role = match spec.device_type {
+            DeviceType::Cpu => "CPU",
+            DeviceType::Gpu => "GPU",
+        };
+        let mut wanted = if spec.pci_ids.is_empty() {
+            format!("{} device matching '{}' with type '{}'", role, spec.device, spec.temp_type)
+        } else {
+            format!("{} device matching '{}' {} with type '{}'", role, spec.device, spec.pci_ids, spec.temp_type)
+        };
+        if let Some(bus) = spec.bus {
+            wanted.push_str(&format!(" on the {} bus", bus));
+        }
+        if let Some(address) = spec.pci_address {
+            wanted.push_str(&format!(" at PCI address {}", address));
+        }
+        if let Some(device) = spec.hwmon_device {
+            wanted.push_str(&format!(" on {}", device.display()));
+        }
+
+        match pick(&candidates) {
+            Ok(i) => found.swap_remove(i).1,
+            Err(Unresolved::NotFound) => {
+                eprintln!("Error: {} not found!", wanted);
+                None
+            }
+            Err(Unresolved::Ambiguous(matches)) => {
+                let names: Vec<String> = matches.iter().map(|&i| candidates[i].to_string()).collect();
+                eprintln!(
+                    "Error: {} is ambiguous, it matches {}. Narrow it down with pci_address, vendor_id/device_id or match = \"exact\"",
+                    wanted, names.join(", ")
+                );
+                None
+            }
+        }
+    });
+    (cpu, gpu)
+}
+
+/// Picks the sensor to use among `candidates`: the only match, or else the
+/// only one whose label is exactly `temp_type`. Anything else is ambiguous,
+/// rather than depending on the order libsensors lists the chips in.
+pub fn pick(candidates: &[Candidate]) -> Result<usize, Unresolved> {
+    let with_fit = |fit: Option<Fit>| -> Vec<usize> {
+        candidates.iter().enumerate()
+            .filter(|(_, c)| c.outcome.as_ref().is_ok_and(|m| fit.is_none_or(|fit| m.fit == fit)))
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    let mut best = with_fit(Some(Fit::Exact));
+    if best.is_empty() {
+        best = with_fit(None);
+    }
+    match best[..] {
+        [] => Err(Unresolved::NotFound),
+        [i] => Ok(i),
+        _ => Err(Unresolved::Ambiguous(best)),
+    }
+}
+
+/// Every sensor considered for `spec`, with why it was or wasn't a match.
+/// `pick` tells which one `discover_features` uses.
+pub fn explain(sensors: &dyn SensorSource, spec: &SensorSpec) -> Vec<Candidate> {
+    walk(&sensors.chips(), sensors.sysfs_root(), spec).into_iter().map(|(candidate, _)| candidate).collect()
+}
+
+// Checks every temperature sensor against `spec`, returning where to read
+// the matches
+fn walk(chips: &[ChipInfo], sysfs_root: &Path, spec: &SensorSpec) -> Vec<(Candidate, Option<SensorRef>)> {
+    let mut candidates = Vec::new();
+
+    for chip in chips {
+        let chip_check = spec.check_chip(chip, sysfs_root);
+
+        for sensor in &chip.temperatures {
+            let input = format!("{}_input", sensor.feature);
+            let outcome = spec.check(&chip.name, &chip.prefix, &sensor.label, chip_check).and_then(|fit| {
+                if !sensor.subfeatures.contains(&input) {
+                    return Err(Mismatch::NoInput);
+                }
+                Ok(Matched { input, value: sensor.input, fit })
+            });
+            let found = outcome.is_ok().then(|| SensorRef { chip: chip.path.clone(), feature: sensor.feature.clone() });
+            candidates.push((Candidate {
+                chip: chip.name.clone(),
+                feature: sensor.feature.clone(),
+                label: sensor.label.clone(),
+                outcome,
+            }, found));
+        }
+    }
+    candidates
+}
+
+// Whether the hwmon at `hwmon_path` is `device`, or belongs to it. Both are
+// resolved, so /sys/class/hwmon/hwmonN and /sys/devices/... paths work.
+fn is_device(hwmon_path: &Path, device: &Path) -> bool {
+    let Ok(device) = fs::canonicalize(device) else {
+        return false;
+    };
+    [hwmon_path.to_path_buf(), hwmon_path.join("device")].iter()
+        .any(|path| fs::canonicalize(path).is_ok_and(|path| path == device))
+}
+
+// Moves a /sys/... path from the config under `sysfs_root`, for when sysfs
+// is mounted elsewhere
+fn rebase(sysfs_root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(source::SYSFS_ROOT) {
+        Ok(rest) => sysfs_root.join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Whether a chip on `bus` is on the configured `wanted` bus. libsensors calls
+// platform devices ISA, so "isa" takes both.
+fn on_bus(bus: Bus, wanted: Bus) -> bool {
+    bus == wanted || (wanted == Bus::Isa && bus == Bus::Platform)
+}
+
+// Whether the chip's device has the expected IDs: the USB IDs of a HID
+// device, or else those of its PCI device
+fn matches_ids(sysfs_root: &Path, chip: &ChipInfo, expected: &PciIds) -> bool {
+    if chip.bus != Bus::Hid {
+        return matches_pci_id(sysfs_root, chip, expected);
+    }
+    let Some(hid) = listing::device_name(&chip.path).as_deref().and_then(listing::parse_hid_name) else {
+        return false;
+    };
+    expected.vendor.is_none_or(|vendor| vendor == hid.vendor)
+        && expected.device.is_none_or(|device| device == hid.product)
+        && expected.subsystem_vendor.is_none()
+        && expected.subsystem_device.is_none()
+}
+
+// Whether the chip's PCI device has the expected IDs. The device is the one
+// the hwmon's device link points to, or failing that the one at the bus
+// address libsensors put in the chip name.
+// IDs are compared as numbers, so "10" doesn't match 0x1002, and an ID that
+// can't be read doesn't match at all.
+fn matches_pci_id(sysfs_root: &Path, chip: &ChipInfo, expected: &PciIds) -> bool {
+    let address = chip.pci_address.clone().or_else(|| pci_address_from_name(sysfs_root, &chip.name));
+    let Some(address) = address else {
+        return false;
+    };
+
+    let path = sysfs_root.join("bus/pci/devices").join(address);
+    let matches = |file: &str, expected: Option<u16>| {
+        let Some(expected) = expected else {
+            return true;
+        };
+        let id = fs::read_to_string(path.join(file)).ok();
+        id.as_deref().and_then(config::parse_hex_id) == Some(expected)
+    };
+    matches("vendor", expected.vendor)
+        && matches("device", expected.device)
+        && matches("subsystem_vendor", expected.subsystem_vendor)
+        && matches("subsystem_device", expected.subsystem_device)
+}
+
+// The PCI address for a name like "amdgpu-pci-0300". The name doesn't say
+// which PCI domain the device is in, so this gives up if several domains
+// have a device at that bus address.
+fn pci_address_from_name(sysfs_root: &Path, chip_name: &str) -> Option<String> {
+    let (_, bus_address) = chip_name.rsplit_once("-pci-")?;
+    if bus_address.len() != 4 {
+        return None;
+    }
+    let bus_address = u16::from_str_radix(bus_address, 16).ok()?;
+
+    let entries = fs::read_dir(sysfs_root.join("bus/pci/devices")).ok()?;
+    let mut found = entries.flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|address| listing::libsensors_bus_address(address) == Some(bus_address));
+    match (found.next(), found.next()) {
+        (Some(address), None) => Some(address),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::fixture::TempDir;
+    use crate::listing::SensorInfo;
+    use crate::source::mock::MockSource;
+
+    fn spec<'a>(device: &'a str, temp_type: &'a str, mode: MatchMode) -> SensorSpec<'a> {
+        let regexes = (mode == MatchMode::Regex).then(|| SensorRegexes {
+            device: config::sensor_regex(device).unwrap(),
+            temp_type: config::sensor_regex(temp_type).unwrap(),
+        });
+        SensorSpec::new(DeviceType::Cpu, device, temp_type, mode, regexes)
+    }
+
+    fn candidate(label: &str, outcome: Result<Fit, Mismatch>) -> Candidate {
+        Candidate {
+            chip: "k10temp-pci-00c3".to_string(),
+            feature: "temp1".to_string(),
+            label: label.to_string(),
+            outcome: outcome.map(|fit| Matched { input: "temp1_input".to_string(), value: Some(40.0), fit }),
+        }
+    }
+
+    #[test]
+    fn test_check_reports_first_mismatch() {
+        let k10temp = spec("k10temp", "tctl", MatchMode::Prefix);
+        assert_eq!(Ok(Fit::Exact), k10temp.check("k10temp-pci-00c3", "k10temp", "Tctl", Ok(())));
+        assert_eq!(Err(Mismatch::Label), k10temp.check("k10temp-pci-00c3", "k10temp", "Tccd1", Ok(())));
+        assert_eq!(Err(Mismatch::Name), k10temp.check("amdgpu-pci-0300", "amdgpu", "edge", Ok(())));
+
+        // The device may also be given as the start of the label
+        let composite = spec("composite", "composite", MatchMode::Prefix);
+        assert_eq!(Ok(Fit::Exact), composite.check("nvme-pci-0100", "nvme", "Composite", Ok(())));
+
+        let pinned = spec("amdgpu", "edge", MatchMode::Prefix);
+        assert_eq!(Err(Mismatch::Id), pinned.check("amdgpu-pci-0300", "amdgpu", "edge", Err(Mismatch::Id)));
+        assert_eq!(Err(Mismatch::Name), pinned.check("k10temp-pci-00c3", "k10temp", "Tctl", Err(Mismatch::Id)));
+    }
+
+    #[test]
+    fn test_check_match_modes() {
+        let prefix = spec("k10", "tccd1", MatchMode::Prefix);
+        assert_eq!(Ok(Fit::Exact), prefix.check("k10temp-pci-00c3", "k10temp", "Tccd1", Ok(())));
+        assert_eq!(Ok(Fit::Prefix), prefix.check("k10temp-pci-00c3", "k10temp", "Tccd10", Ok(())));
+
+        let exact = spec("k10temp", "tccd1", MatchMode::Exact);
+        assert_eq!(Ok(Fit::Exact), exact.check("k10temp-pci-00c3", "k10temp", "Tccd1", Ok(())));
+        assert_eq!(Err(Mismatch::Label), exact.check("k10temp-pci-00c3", "k10temp", "Tccd10", Ok(())));
+        assert_eq!(Err(Mismatch::Name), spec("k10", "tccd1", MatchMode::Exact).check("k10temp-pci-00c3", "k10temp", "Tccd1", Ok(())));
+
+        // Regexes match the whole name or label, ignoring case
+        let regex = spec("k10temp|zenpower", r"Tccd\d", MatchMode::Regex);
+        assert_eq!(Ok(Fit::Exact), regex.check("zenpower-pci-00c3", "zenpower", "TCCD2", Ok(())));
+        assert_eq!(Err(Mismatch::Label), regex.check("k10temp-pci-00c3", "k10temp", "Tccd10", Ok(())));
+        assert_eq!(Err(Mismatch::Name), spec("k10", ".*", MatchMode::Regex).check("k10temp-pci-00c3", "k10temp", "Tctl", Ok(())));
+
+        // A config not built by the parser has no compiled regexes, and
+        // matches nothing rather than panicking
+        let uncompiled = SensorSpec::new(DeviceType::Cpu, "k10temp", "tctl", MatchMode::Regex, None);
+        assert_eq!(Err(Mismatch::Name), uncompiled.check("k10temp-pci-00c3", "k10temp", "Tctl", Ok(())));
+    }
+
+    #[test]
+    fn test_is_device() {
+        let sysfs = TempDir::new("is-device");
+        let gpu = sysfs.device("pci0000:00/0000:00:01.1/0000:03:00.0", None);
+        let hwmon = sysfs.hwmon(&gpu, "hwmon1");
+
+        // The stable device path, or the hwmon directory by any of its paths
+        let by_class = sysfs.path().join("class/hwmon/hwmon1");
+        assert!(is_device(&by_class, &gpu));
+        assert!(is_device(&by_class, &hwmon));
+        assert!(is_device(&hwmon, &by_class));
+        assert!(!is_device(&by_class, &sysfs.path().join("devices/pci0000:00/0000:00:01.1")));
+        assert!(!is_device(&by_class, &sysfs.path().join("missing")));
+    }
+
+    fn chip(name: &str, path: &str, pci_address: &str, sensors: &[(&str, &str, f64)]) -> ChipInfo {
+        ChipInfo {
+            name: name.to_string(),
+            prefix: name.split('-').next().unwrap().to_string(),
+            path: path.into(),
+            bus: Bus::Pci,
+            pci_address: Some(pci_address.to_string()),
+            driver: None,
+            vendor_id: None,
+            device_id: None,
+            subsystem_vendor_id: None,
+            subsystem_device_id: None,
+            temperatures: sensors.iter().map(|&(feature, label, value)| SensorInfo {
+                label: label.to_string(),
+                feature: feature.to_string(),
+                input: Some(value),
+                min: None,
+                max: None,
+                crit: None,
+                subfeatures: vec![format!("{}_input", feature)],
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn test_discover_features() {
+        let mut sensors = MockSource { chips: vec![
+            chip("k10temp-pci-00c3", "/hwmon/hwmon1", "0000:00:18.3", &[("temp1", "Tctl", 50.0), ("temp3", "Tccd1", 45.0)]),
+            chip("amdgpu-pci-0300", "/hwmon/hwmon2", "0000:03:00.0", &[("temp1", "edge", 40.0), ("temp2", "junction", 42.0)]),
+        ], ..Default::default() };
+        let mut config = AppConfig::parse_toml("[cpu]\ndevice = \"k10temp\"\ntemp_type = \"tccd\"\n[gpu]\ndevice = \"amdgpu\"\ntemp_type = \"edge\"\n").unwrap().displays.remove(0);
+
+        let (cpu, gpu) = discover_features(&sensors, &config);
+        assert_eq!(Some(SensorRef { chip: "/hwmon/hwmon1".into(), feature: "temp3".to_string() }), cpu);
+        assert_eq!(Some(40.0), sensors.read(&gpu.unwrap()));
+
+        // A second, identical GPU makes the GPU ambiguous until it's pinned
+        sensors.chips.push(chip("amdgpu-pci-0c00", "/hwmon/hwmon3", "0000:0c:00.0", &[("temp1", "edge", 60.0)]));
+        assert!(discover_features(&sensors, &config).1.is_none());
+        config.gpu_pci_address = Some("0000:0c:00.0".to_string());
+        let gpu = discover_features(&sensors, &config).1.unwrap();
+        assert_eq!(Some(60.0), sensors.read(&gpu));
+
+        let candidates = explain(&sensors, &SensorSpec::of(&config, DeviceType::Gpu));
+        assert_eq!(vec![
+            Err(Mismatch::Name), Err(Mismatch::Name),
+            Err(Mismatch::PciAddress), Err(Mismatch::Label),
+            Ok(Matched { input: "temp1_input".to_string(), value: Some(60.0), fit: Fit::Exact }),
+        ], candidates.into_iter().map(|c| c.outcome).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pins_against_a_sysfs_fixture() {
+        let sysfs = TempDir::new("pins");
+        let gpu = sysfs.device("pci0000:00/0000:00:01.1/0000:03:00.0", Some("pci"));
+        let hwmon = sysfs.hwmon(&gpu, "hwmon2");
+        sysfs.write(gpu.join("vendor"), "0x1002\n");
+        sysfs.write(gpu.join("device"), "0x7550\n");
+
+        let mut amdgpu = chip("amdgpu-pci-0300", "", "0000:03:00.0", &[("temp1", "edge", 40.0)]);
+        amdgpu.path = hwmon;
+        let sensors = MockSource { chips: vec![amdgpu], root: sysfs.path().to_path_buf() };
+        let gpu_of = |toml: &str| {
+            let config = AppConfig::parse_toml(&format!("[cpu]\ndevice = \"k10temp\"\ntemp_type = \"tctl\"\n[gpu]\ndevice = \"amdgpu\"\ntemp_type = \"edge\"\n{}", toml));
+            discover_features(&sensors, &config.unwrap().displays[0]).1
+        };
+
+        assert!(gpu_of("vendor_id = \"1002\"\ndevice_id = \"7550\"\n").is_some());
+        assert!(gpu_of("vendor_id = \"10de\"\n").is_none());
+
+        // Paths in the config name the real /sys, wherever the fixture is
+        assert!(gpu_of("hwmon_device = \"/sys/devices/pci0000:00/0000:00:01.1/0000:03:00.0\"\n").is_some());
+        assert!(gpu_of("hwmon_device = \"/sys/devices/pci0000:00/0000:00:01.1\"\n").is_none());
+    }
+
+    #[test]
+    fn test_non_pci_chips() {
+        let sysfs = TempDir::new("non-pci");
+        let mut chips = Vec::new();
+        for (i, (name, device, subsystem, label)) in [
+            ("nct6775-isa-0290", "platform/nct6775.656", "platform", "SYSTIN"),
+            ("nzxtkraken3-hid-3-1", "pci0000:00/0000:00:14.0/usb1/1-9/1-9:1.0/0003:1E71:2007.0001", "hid", "Coolant temp"),
+            ("nzxtkraken3-hid-3-2", "pci0000:00/0000:00:14.0/usb1/1-10/1-10:1.0/0003:1E71:3008.0002", "hid", "Coolant temp"),
+            ("acpitz-acpi-0", "LNXSYSTM:00/LNXTHERM:00", "acpi", "temp1"),
+        ].into_iter().enumerate() {
+            let hwmon = sysfs.hwmon(&sysfs.device(device, Some(subsystem)), &format!("hwmon{}", i));
+            let mut info = ChipInfo::new(name.to_string(), name.split('-').next().unwrap().to_string(), hwmon);
+            info.temperatures = chip(name, "", "", &[("temp1", label, 30.0)]).temperatures;
+            chips.push(info);
+        }
+        let sensors = MockSource { chips, root: sysfs.path().to_path_buf() };
+        let gpu_of = |toml: &str| {
+            let config = AppConfig::parse_toml(&format!("[cpu]\ndevice = \"k10temp\"\ntemp_type = \"tctl\"\n[gpu]\n{}", toml));
+            discover_features(&sensors, &config.unwrap().displays[0]).1.map(|sensor| sensor.chip)
+        };
+        let hwmon_of = |chip: &str| sensors.chips.iter().find(|c| c.name == chip).map(|c| c.path.clone());
+
+        // Two coolers by the same vendor, told apart by their USB product IDs
+        let coolant = "device = \"nzxtkraken3\"\ntemp_type = \"coolant\"\n";
+        assert_eq!(None, gpu_of(coolant));
+        assert_eq!(None, gpu_of(&format!("{}vendor_id = \"1e71\"\n", coolant)));
+        assert_eq!(hwmon_of("nzxtkraken3-hid-3-2"),
+                   gpu_of(&format!("{}vendor_id = \"1e71\"\ndevice_id = \"3008\"\n", coolant)));
+        // USB devices have no subsystem IDs
+        assert_eq!(None, gpu_of(&format!("{}device_id = \"3008\"\nsubsystem_vendor_id = \"1e71\"\n", coolant)));
+
+        // libsensors calls platform devices ISA
+        let systin = "device = \"nct6775\"\ntemp_type = \"systin\"\n";
+        assert_eq!(hwmon_of("nct6775-isa-0290"), gpu_of(&format!("{}bus = \"isa\"\n", systin)));
+        assert_eq!(hwmon_of("nct6775-isa-0290"), gpu_of(&format!("{}bus = \"platform\"\n", systin)));
+        assert_eq!(None, gpu_of(&format!("{}bus = \"acpi\"\n", systin)));
+        // Chips without PCI or USB IDs never match them
+        assert_eq!(None, gpu_of(&format!("{}vendor_id = \"1002\"\n", systin)));
+        assert_eq!(hwmon_of("acpitz-acpi-0"), gpu_of("device = \"acpitz\"\ntemp_type = \"temp1\"\nbus = \"ACPI\"\n"));
+    }
+
+    fn ids(vendor: u16, device: u16) -> PciIds {
+        PciIds { vendor: Some(vendor), device: Some(device), ..Default::default() }
+    }
+
+    #[test]
+    fn test_matches_pci_id() {
+        let sysfs = TempDir::new("pci-id");
+        for (address, vendor, device, subsystem_vendor) in [
+            ("0000:0c:00.0", "0x1002", "0x7550", "0x1da2"),
+            ("0000:0c:00.1", "0x1002", "0xab30", "0x1da2"),
+            ("0000:00:18.3", "0x1022", "0x14e3", "0x0000"),
+            ("0000:03:00.0", "0x10de", "0x2684", "0x10de"),
+            ("0001:03:00.0", "0x1002", "0x744c", "0x1002"),
+        ] {
+            let pci_device = sysfs.device(&format!("pci0000:00/{}", address), Some("pci"));
+            sysfs.write(pci_device.join("vendor"), &format!("{}\n", vendor));
+            sysfs.write(pci_device.join("device"), &format!("{}\n", device));
+            sysfs.write(pci_device.join("subsystem_vendor"), &format!("{}\n", subsystem_vendor));
+        }
+        let root = sysfs.path();
+        let named = |name: &str| ChipInfo { pci_address: None, ..chip(name, "", "", &[]) };
+
+        // From the bus address in the name: bus 0c is not "0c" + "00" + "0"
+        assert!(matches_pci_id(root, &named("amdgpu-pci-0c00"), &ids(0x1002, 0x7550)));
+        assert!(matches_pci_id(root, &named("k10temp-pci-00c3"), &ids(0x1022, 0x14e3)));
+        // The second function of the same slot is another device
+        assert!(matches_pci_id(root, &named("snd-pci-0c01"), &ids(0x1002, 0xab30)));
+        assert!(!matches_pci_id(root, &named("snd-pci-0c01"), &ids(0x1002, 0x7550)));
+        // 0300 is in two domains, so only the device link can tell
+        assert!(!matches_pci_id(root, &named("amdgpu-pci-0300"), &ids(0x1002, 0x744c)));
+        assert!(matches_pci_id(root, &chip("amdgpu-pci-0300", "", "0001:03:00.0", &[]), &ids(0x1002, 0x744c)));
+        assert!(!matches_pci_id(root, &chip("amdgpu-pci-0300", "", "0000:03:00.0", &[]), &ids(0x1002, 0x744c)));
+
+        // IDs are compared whole, not as substrings of the sysfs files
+        let gpu = named("amdgpu-pci-0c00");
+        let vendor_only = |vendor| PciIds { vendor: Some(vendor), ..Default::default() };
+        assert!(matches_pci_id(root, &gpu, &vendor_only(0x1002)));
+        assert!(!matches_pci_id(root, &gpu, &vendor_only(0x10)));
+        assert!(!matches_pci_id(root, &gpu, &vendor_only(0x100)));
+
+        // The subsystem tells a board partner's card from the reference one
+        let partner = |subsystem_vendor| PciIds { subsystem_vendor: Some(subsystem_vendor), ..ids(0x1002, 0x7550) };
+        assert!(matches_pci_id(root, &gpu, &partner(0x1da2)));
+        assert!(!matches_pci_id(root, &gpu, &partner(0x1002)));
+        // An ID that isn't in sysfs can't match
+        assert!(!matches_pci_id(root, &gpu, &PciIds { subsystem_device: Some(0x475e), ..partner(0x1da2) }));
+    }
+
+    #[test]
+    fn test_pick() {
+        let label_mismatch = candidate("Tctl", Err(Mismatch::Label));
+        let exact = candidate("Tccd1", Ok(Fit::Exact));
+        let prefix = candidate("Tccd10", Ok(Fit::Prefix));
+
+        assert_eq!(Err(Unresolved::NotFound), pick(std::slice::from_ref(&label_mismatch)));
+        assert_eq!(Ok(1), pick(&[label_mismatch, prefix.clone()]));
+
+        // An exact label wins over prefix matches, wherever it is listed
+        assert_eq!(Ok(1), pick(&[prefix.clone(), exact.clone(), prefix.clone()]));
+
+        // Two equally good matches are an error, not the last one silently
+        assert_eq!(Err(Unresolved::Ambiguous(vec![0, 2])), pick(&[exact.clone(), prefix.clone(), exact]));
+        assert_eq!(Err(Unresolved::Ambiguous(vec![0, 1])), pick(&[prefix.clone(), prefix]));
+    }
+}
@@ -0,0 +1,79 @@
+//! Throwaway directories for tests, such as a fake /sys tree.
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// A directory under the system temp dir, deleted again when dropped, even
+/// if the test fails.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory. `name` has to be unique among the tests,
+    /// since they run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("antec-flux-pro-display-{}-{}", name, std::process::id()));
+        // Left over from a run that was killed
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates a directory and its parents. Relative paths are taken from
+    /// the temp dir, as for every method here.
+    pub fn dir(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = self.path.join(path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Writes a file, creating its parents.
+    pub fn write(&self, path: impl AsRef<Path>, contents: &str) -> PathBuf {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Links `link` to `target`, creating both their parents.
+    pub fn symlink(&self, target: impl AsRef<Path>, link: impl AsRef<Path>) {
+        let (target, link) = (self.path.join(target), self.path.join(link));
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        symlink(target, link).unwrap();
+    }
+
+    /// Adds a device under devices/, e.g. "pci0000:00/0000:00:01.1/0000:03:00.0".
+    /// With a subsystem, it's linked to bus/<subsystem> and listed in
+    /// bus/<subsystem>/devices, as sysfs does.
+    pub fn device(&self, path: &str, subsystem: Option<&str>) -> PathBuf {
+        let device = self.dir(Path::new("devices").join(path));
+        if let Some(subsystem) = subsystem {
+            let bus = self.dir(Path::new("bus").join(subsystem).join("devices"));
+            self.symlink(bus.parent().unwrap(), device.join("subsystem"));
+            self.symlink(&device, bus.join(device.file_name().unwrap()));
+        }
+        device
+    }
+
+    /// Adds a hwmon directory, e.g. "hwmon2", under `device`, with its
+    /// `device` link and its entry in class/hwmon.
+    pub fn hwmon(&self, device: &Path, name: &str) -> PathBuf {
+        let hwmon = self.dir(device.join("hwmon").join(name));
+        self.symlink(device, hwmon.join("device"));
+        self.symlink(&hwmon, Path::new("class/hwmon").join(name));
+        hwmon
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
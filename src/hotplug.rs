@@ -0,0 +1,108 @@
+use std::sync::Once;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use rusb::{GlobalContext, Hotplug, HotplugBuilder, Registration, UsbContext};
+
+use crate::usb::{UsbError, UsbLocation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugEvent {
+    Arrived(UsbLocation),
+    Left(UsbLocation),
+}
+
+/// Something that reports when a display is plugged in or unplugged.
+pub trait HotplugSource {
+    /// Returns the events received since the last call, without blocking.
+    fn poll_events(&mut self) -> Vec<HotplugEvent>;
+}
+
+// For running without hotplug support, where there are never any events
+impl<H: HotplugSource> HotplugSource for Option<H> {
+    fn poll_events(&mut self) -> Vec<HotplugEvent> {
+        self.as_mut().map(H::poll_events).unwrap_or_default()
+    }
+}
+
+struct Callback {
+    sender: Sender<HotplugEvent>,
+}
+
+impl Hotplug<GlobalContext> for Callback {
+    fn device_arrived(&mut self, device: rusb::Device<GlobalContext>) {
+        self.sender.send(HotplugEvent::Arrived(UsbLocation::of(&device))).ok();
+    }
+
+    fn device_left(&mut self, device: rusb::Device<GlobalContext>) {
+        self.sender.send(HotplugEvent::Left(UsbLocation::of(&device))).ok();
+    }
+}
+
+// libusb only runs callbacks while something is handling its events. One
+// thread does that for every registration, however often they're replaced.
+static EVENT_THREAD: Once = Once::new();
+
+/// Watches for displays using libusb hotplug callbacks.
+pub struct UsbHotplug {
+    receiver: Receiver<HotplugEvent>,
+    // Dropping a registration deregisters its callback
+    _registrations: Vec<Registration<GlobalContext>>,
+}
+
+impl UsbHotplug {
+    /// Registers arrival/departure callbacks for each vendor/product ID pair.
+    /// Returns `None` if this platform's libusb doesn't support hotplug.
+    pub fn register(ids: &[(u16, u16)]) -> Result<Option<Self>, UsbError> {
+        if !rusb::has_hotplug() {
+            return Ok(None);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut registrations = Vec::new();
+        for &(vendor_id, product_id) in ids {
+            let callback = Box::new(Callback { sender: sender.clone() });
+            registrations.push(HotplugBuilder::new()
+                .vendor_id(vendor_id)
+                .product_id(product_id)
+                .register(GlobalContext::default(), callback)?);
+        }
+
+        EVENT_THREAD.call_once(|| {
+            thread::spawn(|| loop {
+                GlobalContext::default().handle_events(Some(Duration::from_secs(1))).ok();
+            });
+        });
+
+        Ok(Some(Self {
+            receiver,
+            _registrations: registrations,
+        }))
+    }
+}
+
+impl HotplugSource for UsbHotplug {
+    fn poll_events(&mut self) -> Vec<HotplugEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Hands out queued events, one batch per `poll_events` call.
+    #[derive(Debug, Default)]
+    pub struct MockHotplug {
+        pub batches: VecDeque<Vec<HotplugEvent>>,
+    }
+
+    impl HotplugSource for MockHotplug {
+        fn poll_events(&mut self) -> Vec<HotplugEvent> {
+            self.batches.pop_front().unwrap_or_default()
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use sensors::{FeatureType, Sensors, SubfeatureType};
+
+use crate::listing::{ChipInfo, SensorInfo};
+use crate::source::{self, SensorRef, SensorSource};
+
+/// Sensors as seen by libsensors.
+///
+/// libsensors only scans for chips once per process: the `sensors` crate
+/// initialises it behind a `Once`, so creating another `Sensors` doesn't
+/// rescan. Chips that come back after a driver reload keep their old handles
+/// until a restart, which the sysfs backend doesn't need.
+pub struct Libsensors {
+    sensors: Sensors,
+}
+
+impl Libsensors {
+    pub fn new() -> Self {
+        Libsensors { sensors: Sensors::new() }
+    }
+}
+
+impl SensorSource for Libsensors {
+    fn chips(&self) -> Vec<ChipInfo> {
+        let mut chips = Vec::new();
+
+        for chip in self.sensors.into_iter() {
+            let mut info = ChipInfo::new(
+                chip.get_name().unwrap_or_else(|_| chip.prefix().to_string()),
+                chip.prefix().to_string(),
+                chip.path().to_path_buf(),
+            );
+
+            for feature in chip {
+                if *feature.feature_type() != FeatureType::SENSORS_FEATURE_TEMP {
+                    continue;
+                }
+                let Ok(label) = feature.get_label() else {
+                    continue;
+                };
+                let value = |subfeature_type| feature.get_subfeature(subfeature_type)
+                    .and_then(|sf| sf.get_value().ok());
+                info.temperatures.push(SensorInfo {
+                    label,
+                    feature: feature.name().to_string(),
+                    input: value(SubfeatureType::SENSORS_SUBFEATURE_TEMP_INPUT),
+                    min: value(SubfeatureType::SENSORS_SUBFEATURE_TEMP_MIN),
+                    max: value(SubfeatureType::SENSORS_SUBFEATURE_TEMP_MAX),
+                    crit: value(SubfeatureType::SENSORS_SUBFEATURE_TEMP_CRIT),
+                    subfeatures: feature.into_iter().map(|sf| sf.name().to_string()).collect(),
+                });
+            }
+            chips.push(info);
+        }
+        chips
+    }
+
+    fn read(&self, sensor: &SensorRef) -> Option<f64> {
+        let chip = self.sensors.into_iter().find(|chip| chip.path() == sensor.chip)?;
+        let feature = chip.into_iter().find(|feature| feature.name() == sensor.feature)?;
+        feature.get_subfeature(SubfeatureType::SENSORS_SUBFEATURE_TEMP_INPUT)?.get_value().ok()
+    }
+
+    fn rescans(&self) -> bool {
+        false
+    }
+
+    fn sysfs_root(&self) -> &Path {
+        Path::new(source::SYSFS_ROOT)
+    }
+}
@@ -0,0 +1,350 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// A hwmon chip and its temperature sensors, as shown by `list-sensors`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChipInfo {
+    /// Full libsensors name, e.g. "amdgpu-pci-0300"
+    pub name: String,
+    /// The name without the bus part, as used for `device` in the config
+    pub prefix: String,
+    pub path: PathBuf,
+    pub bus: Bus,
+    pub pci_address: Option<String>,
+    pub driver: Option<String>,
+    /// PCI IDs, or the USB IDs of a HID device
+    pub vendor_id: Option<String>,
+    pub device_id: Option<String>,
+    /// Board partner and model, as matched by `subsystem_vendor_id` and
+    /// `subsystem_device_id`
+    pub subsystem_vendor_id: Option<String>,
+    pub subsystem_device_id: Option<String>,
+    pub temperatures: Vec<SensorInfo>,
+}
+
+/// The bus a chip's parent device is on, from its sysfs subsystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Pci,
+    Isa,
+    /// e.g. SuperIO chips like nct6775, or coretemp. libsensors names these
+    /// "isa" like real ISA devices.
+    Platform,
+    Acpi,
+    /// e.g. USB liquid coolers
+    Hid,
+    I2c,
+    Spi,
+    /// No parent device, or one on a bus libsensors doesn't name
+    Virtual,
+}
+
+impl Bus {
+    pub const ALL: [Bus; 8] = [Bus::Pci, Bus::Isa, Bus::Platform, Bus::Acpi, Bus::Hid, Bus::I2c, Bus::Spi, Bus::Virtual];
+
+    /// The bus type in libsensors' chip names, e.g. "isa" in
+    /// "nct6775-isa-0290".
+    pub fn libsensors_name(self) -> &'static str {
+        match self {
+            Bus::Pci => "pci",
+            Bus::Isa | Bus::Platform => "isa",
+            Bus::Acpi => "acpi",
+            Bus::Hid => "hid",
+            Bus::I2c => "i2c",
+            Bus::Spi => "spi",
+            Bus::Virtual => "virtual",
+        }
+    }
+}
+
+impl fmt::Display for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Bus::Platform => "platform",
+            bus => bus.libsensors_name(),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One temperature sensor of a chip. Values are in °C, `None` if the chip
+/// doesn't report them or reading failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorInfo {
+    /// As used for `temp_type` in the config, e.g. "Tctl"
+    pub label: String,
+    /// hwmon feature name, e.g. "temp1"
+    pub feature: String,
+    pub input: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub crit: Option<f64>,
+    pub subfeatures: Vec<String>,
+}
+
+impl ChipInfo {
+    /// A chip without temperatures yet, with the details of its parent
+    /// device read from sysfs.
+    pub fn new(name: String, prefix: String, path: PathBuf) -> Self {
+        let bus = bus(&path);
+        let hid_ids = match bus {
+            Bus::Hid => device_name(&path).as_deref().and_then(parse_hid_name),
+            _ => None,
+        };
+        ChipInfo {
+            name,
+            prefix,
+            bus,
+            pci_address: pci_address(&path),
+            driver: driver(&path),
+            vendor_id: pci_id(&path, "vendor").or_else(|| hid_ids.map(|ids| format!("{:04x}", ids.vendor))),
+            device_id: pci_id(&path, "device").or_else(|| hid_ids.map(|ids| format!("{:04x}", ids.product))),
+            subsystem_vendor_id: pci_id(&path, "subsystem_vendor"),
+            subsystem_device_id: pci_id(&path, "subsystem_device"),
+            path,
+            temperatures: Vec::new(),
+        }
+    }
+}
+
+/// Prints the chips in the human-readable startup format.
+pub fn print_text(chips: &[ChipInfo]) {
+    println!("Available temperature sensors:");
+    println!("-----------------------------");
+
+    for chip in chips {
+        println!("Chip: {}", chip.name);
+        println!("  Path: {}", chip.path.display());
+        println!("  Bus: {}", chip.bus);
+
+        if let Some(pci_address) = &chip.pci_address {
+            println!("  PCI address: {}", pci_address);
+        }
+
+        if let Some(vendor_id) = &chip.vendor_id {
+            println!("  VendorId: {}", vendor_id);
+        }
+
+        if let Some(device_id) = &chip.device_id {
+            println!("  DeviceId: {}", device_id);
+        }
+
+        if let Some(vendor_id) = &chip.subsystem_vendor_id {
+            println!("  SubsystemVendorId: {}", vendor_id);
+        }
+
+        if let Some(device_id) = &chip.subsystem_device_id {
+            println!("  SubsystemDeviceId: {}", device_id);
+        }
+
+        println!("  Temperatures:");
+        for sensor in &chip.temperatures {
+            if let Some(temp) = sensor.input {
+                println!("    {}: {:.1}°C", sensor.label, temp);
+            }
+        }
+    }
+
+    println!("-----------------------------");
+}
+
+// One of the parent device's IDs, e.g. its "vendor" file, which holds a hex
+// string like "0x1002\n"
+fn pci_id(hwmon_path: &Path, file: &str) -> Option<String> {
+    let id = fs::read_to_string(hwmon_path.join("device").join(file)).ok()?;
+    Some(id.trim().trim_start_matches("0x").to_string()).filter(|id| !id.is_empty())
+}
+
+/// PCI address (e.g. "0000:03:00.0") of the hwmon's parent device, if it's
+/// a PCI device.
+pub fn pci_address(hwmon_path: &Path) -> Option<String> {
+    device_name(hwmon_path).filter(|name| is_pci_address(name))
+}
+
+/// The kernel's name for the hwmon's parent device, e.g. "0000:03:00.0",
+/// "nct6775.656" or "0003:1E71:2007.0001".
+pub fn device_name(hwmon_path: &Path) -> Option<String> {
+    let device = fs::canonicalize(hwmon_path.join("device")).ok()?;
+    Some(device.file_name()?.to_str()?.to_string())
+}
+
+/// The bus of the hwmon's parent device.
+pub fn bus(hwmon_path: &Path) -> Bus {
+    let Ok(subsystem) = fs::read_link(hwmon_path.join("device/subsystem")) else {
+        return Bus::Virtual;
+    };
+    match subsystem.file_name().and_then(|name| name.to_str()) {
+        Some("pci") => Bus::Pci,
+        Some("isa") => Bus::Isa,
+        Some("platform") => Bus::Platform,
+        Some("acpi") => Bus::Acpi,
+        Some("hid") => Bus::Hid,
+        Some("i2c") => Bus::I2c,
+        Some("spi") => Bus::Spi,
+        _ => Bus::Virtual,
+    }
+}
+
+/// The parts of a HID device name like "0003:1E71:2007.0001".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HidName {
+    /// The HID bus type, 3 for USB
+    pub bus_type: u16,
+    pub vendor: u16,
+    pub product: u16,
+    /// Counts up as HID devices are plugged in
+    pub instance: u32,
+}
+
+pub fn parse_hid_name(name: &str) -> Option<HidName> {
+    let (ids, instance) = name.split_once('.')?;
+    let mut ids = ids.split(':').map(|id| u16::from_str_radix(id, 16).ok().filter(|_| id.len() == 4));
+    let (Some(Some(bus_type)), Some(Some(vendor)), Some(Some(product)), None) = (ids.next(), ids.next(), ids.next(), ids.next()) else {
+        return None;
+    };
+    let instance = u32::from_str_radix(instance, 16).ok()?;
+    Some(HidName { bus_type, vendor, product, instance })
+}
+
+// Name of the kernel driver bound to the hwmon's parent device
+fn driver(hwmon_path: &Path) -> Option<String> {
+    let driver = fs::read_link(hwmon_path.join("device/driver")).ok()?;
+    Some(driver.file_name()?.to_str()?.to_string())
+}
+
+/// Checks for the domain:bus:device.function form, e.g. "0000:03:00.0".
+pub fn is_pci_address(name: &str) -> bool {
+    parse_pci_address(name).is_some()
+}
+
+/// The number libsensors shows for a PCI chip in its name, e.g. 0c0a in
+/// "amdgpu-pci-0c0a" for "0000:0c:01.2". It packs the bus, device and
+/// function as `(bus << 8) | (device << 3) | function`, leaving out the
+/// domain.
+pub fn libsensors_bus_address(pci_address: &str) -> Option<u16> {
+    let [_, bus, device, function] = parse_pci_address(pci_address)?;
+    Some((bus << 8) | (device << 3) | function)
+}
+
+// Domain, bus, device and function of an address like "0000:03:00.0"
+fn parse_pci_address(name: &str) -> Option<[u16; 4]> {
+    let parts: Vec<&str> = name.split([':', '.']).collect();
+    let lengths = parts.iter().map(|p| p.len()).collect::<Vec<_>>();
+    if lengths != [4, 2, 2, 1] {
+        return None;
+    }
+    let mut fields = [0; 4];
+    for (field, part) in fields.iter_mut().zip(&parts) {
+        if !part.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        *field = u16::from_str_radix(part, 16).ok()?;
+    }
+    // 5 bits of device and 3 of function
+    let [_, _, device, function] = fields;
+    (device < 0x20 && function < 0x08).then_some(fields)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempDir;
+
+    #[test]
+    fn test_pci_address_and_driver() {
+        let sysfs = TempDir::new("listing");
+        let pci_device = sysfs.device("pci0000:00/0000:00:01.1/0000:03:00.0", Some("pci"));
+        sysfs.symlink(sysfs.dir("bus/pci/drivers/amdgpu"), pci_device.join("driver"));
+        let gpu = sysfs.hwmon(&pci_device, "hwmon1");
+        let coretemp = sysfs.hwmon(&sysfs.device("platform/coretemp.0", None), "hwmon2");
+
+        assert_eq!(Some("0000:03:00.0".to_string()), pci_address(&gpu));
+        assert_eq!(Some("amdgpu".to_string()), driver(&gpu));
+        assert_eq!(None, pci_address(&coretemp));
+        assert_eq!(None, driver(&coretemp));
+        assert_eq!((Bus::Pci, Bus::Virtual), (bus(&gpu), bus(&coretemp)));
+        assert_eq!(Some("coretemp.0".to_string()), device_name(&coretemp));
+    }
+
+    #[test]
+    fn test_parse_hid_name() {
+        assert_eq!(Some(HidName { bus_type: 3, vendor: 0x1e71, product: 0x2007, instance: 0x1a }), parse_hid_name("0003:1E71:2007.001A"));
+        assert_eq!(None, parse_hid_name("0003:1E71:2007"));
+        assert_eq!(None, parse_hid_name("0003:1E71.0001"));
+        assert_eq!(None, parse_hid_name("0003:1E71:2007:0000.0001"));
+        assert_eq!(None, parse_hid_name("nct6775.656"));
+    }
+
+    #[test]
+    fn test_libsensors_bus_address() {
+        assert_eq!(Some(0x0300), libsensors_bus_address("0000:03:00.0"));
+        // Multi-function devices, e.g. a GPU's audio function
+        assert_eq!(Some(0x0301), libsensors_bus_address("0000:03:00.1"));
+        assert_eq!(Some(0x00c3), libsensors_bus_address("0000:00:18.3"));
+        // Buses above 0x09 are hex, not decimal
+        assert_eq!(Some(0x0c0a), libsensors_bus_address("0000:0c:01.2"));
+        assert_eq!(Some(0xff00), libsensors_bus_address("0000:ff:00.0"));
+        // The domain isn't part of it
+        assert_eq!(Some(0x0300), libsensors_bus_address("0002:03:00.0"));
+        assert_eq!(Some(0x41f9), libsensors_bus_address("0001:41:1f.1"));
+
+        assert_eq!(None, libsensors_bus_address("0000:03:20.0"));
+        assert_eq!(None, libsensors_bus_address("0000:03:00.8"));
+        assert_eq!(None, libsensors_bus_address("coretemp.0"));
+        assert!(is_pci_address("0000:0a:1f.7"));
+        assert!(!is_pci_address("0000:3:00.0"));
+    }
+
+    #[test]
+    fn test_json_fields() {
+        let chip = ChipInfo {
+            name: "k10temp-pci-00c3".to_string(),
+            prefix: "k10temp".to_string(),
+            path: PathBuf::from("/sys/class/hwmon/hwmon2"),
+            bus: Bus::Pci,
+            pci_address: Some("0000:00:18.3".to_string()),
+            driver: Some("k10temp".to_string()),
+            vendor_id: Some("1022".to_string()),
+            device_id: Some("14e3".to_string()),
+            subsystem_vendor_id: None,
+            subsystem_device_id: None,
+            temperatures: vec![SensorInfo {
+                label: "Tctl".to_string(),
+                feature: "temp1".to_string(),
+                input: Some(45.5),
+                min: None,
+                max: Some(70.0),
+                crit: None,
+                subfeatures: vec!["temp1_input".to_string(), "temp1_max".to_string()],
+            }],
+        };
+
+        let json: serde_json::Value = serde_json::to_value([chip]).unwrap();
+
+        assert_eq!(serde_json::json!([{
+            "name": "k10temp-pci-00c3",
+            "prefix": "k10temp",
+            "path": "/sys/class/hwmon/hwmon2",
+            "bus": "pci",
+            "pci_address": "0000:00:18.3",
+            "driver": "k10temp",
+            "vendor_id": "1022",
+            "device_id": "14e3",
+            "subsystem_vendor_id": null,
+            "subsystem_device_id": null,
+            "temperatures": [{
+                "label": "Tctl",
+                "feature": "temp1",
+                "input": 45.5,
+                "min": null,
+                "max": 70.0,
+                "crit": null,
+                "subfeatures": ["temp1_input", "temp1_max"],
+            }],
+        }]), json);
+    }
+}
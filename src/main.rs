@@ -1,125 +1,116 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set by --quiet. Silences informational output, but not warnings or errors.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// println! for informational output
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::QUIET.load(::std::sync::atomic::Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod autodetect;
+mod cli;
+mod config;
+mod connection;
+mod discovery;
+#[cfg(test)]
+mod fixture;
+mod hotplug;
+#[cfg(feature = "libsensors")]
+mod libsensors;
+mod listing;
+mod protocol;
+mod reload;
+mod setup;
+mod source;
+mod sysfs;
+mod transport;
 mod usb;
 
 use std::error::Error;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::collections::HashMap;
 use std::io;
+use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use sensors::{Chip, Subfeature, Sensors};
-use usb::UsbDevice;
-
-#[derive(Debug)]
-struct AppConfig {
-    cpu_device: String,
-    cpu_temp_type: String,
-    cpu_vendor_id: String,
-    cpu_device_id: String,
-    gpu_device: String,
-    gpu_temp_type: String,
-    gpu_vendor_id: String,
-    gpu_device_id: String,
-    update_interval: u64,
-}
 
-enum DeviceType {
-    Cpu,
-    Gpu,
+use cli::{CheckArgs, Cli, Command, Format, ListArgs, RunArgs, SendArgs};
+use config::{AppConfig, DeviceType, DisplayConfig};
+use connection::Connection;
+use discovery::{SensorSpec, Unresolved, discover_features};
+use hotplug::{HotplugSource, UsbHotplug};
+use protocol::{DecodeError, Frame, Temperatures};
+use reload::ConfigWatcher;
+use source::{SensorRef, SensorSource};
+use transport::DisplayTransport;
+use usb::{UsbDevice, UsbError};
+
+// One case display together with the sensors shown on it
+struct Panel<T: DisplayTransport> {
+    config: DisplayConfig,
+    display: Connection<T>,
+    cpu_feature: Option<SensorRef>,
+    gpu_feature: Option<SensorRef>,
 }
 
-impl AppConfig {
-    fn new() -> io::Result<Self> {
-        let config_dir = PathBuf::from("/etc/antec-flux-pro-display");
-        let config_path = config_dir.join("config.conf");
-
-        if !config_path.exists() {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Configuration file /etc/antec-flux-pro-display/config.conf not found"
-            ));
+impl<T: DisplayTransport> Panel<T> {
+    fn new(config: &DisplayConfig) -> Self {
+        Panel {
+            config: config.clone(),
+            display: Connection::new(config.usb.clone()),
+            cpu_feature: None,
+            gpu_feature: None,
         }
+    }
 
-        let config_str = fs::read_to_string(config_path)?;
-        let mut config_map = HashMap::new();
-
-        for line in config_str.lines() {
-            // Skip empty lines and comments
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+    fn discover_features(&mut self, sensors: &dyn SensorSource) {
+        self.set_features(discover_features(sensors, &self.config));
+    }
 
-            if let Some((key, value)) = line.split_once('=') {
-                config_map.insert(key.trim().to_string(), value.trim().to_string());
-            }
-        }
+    fn set_features(&mut self, (cpu, gpu): (Option<SensorRef>, Option<SensorRef>)) {
+        self.cpu_feature = cpu;
+        self.gpu_feature = gpu;
+    }
 
-        // Get required values or return error if not found
-
-        // Required CPU values
-        let cpu_device = config_map.get("cpu_device")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cpu_device not found in config"))?
-            .clone()
-            .to_lowercase();
-
-        let cpu_temp_type = config_map.get("cpu_temp_type")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cpu_temp_type not found in config"))?
-            .clone()
-            .to_lowercase();
-
-        // Optional CPU values (default to empty string if not found)
-        let cpu_vendor_id = config_map.get("cpu_vendor_id").cloned().unwrap_or_default().trim_start_matches("0x").trim().to_lowercase();
-        let cpu_device_id = config_map.get("cpu_device_id").cloned().unwrap_or_default().trim_start_matches("0x").trim().to_lowercase();
-
-        // Required GPU values
-        let gpu_device = config_map.get("gpu_device")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "gpu_device not found in config"))?
-            .clone()
-            .to_lowercase();
-
-        let gpu_temp_type = config_map.get("gpu_temp_type")
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "gpu_temp_type not found in config"))?
-            .clone()
-            .to_lowercase();
-
-        // Optional GPU values (default to empty string if not found)
-        let gpu_vendor_id = config_map.get("gpu_vendor_id").cloned().unwrap_or_default().trim_start_matches("0x").trim().to_lowercase();
-        let gpu_device_id = config_map.get("gpu_device_id").cloned().unwrap_or_default().trim_start_matches("0x").trim().to_lowercase();
-
-        // Update interval is optional, default to 1000ms if not found
-        let update_interval = config_map.get("update_interval")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(1000);
-
-        Ok(AppConfig {
-            cpu_device,
-            cpu_temp_type,
-            cpu_vendor_id,
-            cpu_device_id,
-            gpu_device,
-            gpu_temp_type,
-            gpu_vendor_id,
-            gpu_device_id,
-            update_interval,
-        })
+    // Whether at least one of the display's sensors was found
+    fn has_sensors(&self) -> bool {
+        self.cpu_feature.is_some() || self.gpu_feature.is_some()
     }
+}
 
-    fn format_device_info(&self, device_type: DeviceType) -> String {
-        let (name, temp_type, vendor, device) = match device_type {
-            DeviceType::Cpu => (&self.cpu_device, &self.cpu_temp_type, &self.cpu_vendor_id, &self.cpu_device_id),
-            DeviceType::Gpu => (&self.gpu_device, &self.gpu_temp_type, &self.gpu_vendor_id, &self.gpu_device_id),
-        };
+// Builds the panels for a reloaded config. A display whose USB selection is
+// unchanged keeps its open connection, so it doesn't flicker.
+fn rebuild_panels<T: DisplayTransport>(old: Vec<Panel<T>>, displays: &[DisplayConfig]) -> Vec<Panel<T>> {
+    let mut old: Vec<Option<Panel<T>>> = old.into_iter().map(Some).collect();
+
+    displays.iter()
+        .map(|config| {
+            let reused = old.iter_mut()
+                .find(|panel| panel.as_ref().is_some_and(|p| p.config.usb == config.usb))
+                .and_then(Option::take);
+            match reused {
+                Some(mut panel) => {
+                    panel.config = config.clone();
+                    panel
+                }
+                None => Panel::new(config),
+            }
+        })
+        .collect()
+}
 
-        let mut out = format!("{} (type: {})", name, temp_type);
-        if !vendor.is_empty() || !device.is_empty() {
-            out.push_str(&format!(" [ID: {}:{}]",
-                                  if vendor.is_empty() { "*" } else { vendor },
-                                  if device.is_empty() { "*" } else { device }
-            ));
-        }
-        out
-    }
+// Vendor/product ID pairs to watch for hotplug events
+fn usb_ids(config: &AppConfig) -> Vec<(u16, u16)> {
+    let mut usb_ids: Vec<(u16, u16)> = config.displays.iter()
+        .map(|d| (d.usb.vendor_id, d.usb.product_id))
+        .collect();
+    usb_ids.sort();
+    usb_ids.dedup();
+    usb_ids
 }
 
 fn get_time_string() -> String {
@@ -133,251 +124,307 @@ fn get_time_string() -> String {
             secs % 60)
 }
 
-// Helper function to find sensors
-fn discover_features(sensors: &Sensors, config: &AppConfig) -> (Option<Subfeature>, Option<Subfeature>) {
-    let mut cpu: Option<Subfeature> = None;
-    let mut gpu: Option<Subfeature> = None;
-
-    for chip in sensors.clone().into_iter() {
-        let chip_name = chip.get_name().unwrap_or_default().to_lowercase();
-
-        // CPU PCI Vendor/Device check
-        let cpu_v_empty = config.cpu_vendor_id.is_empty();
-        let cpu_d_empty = config.cpu_device_id.is_empty();
-
-        let is_cpu_pci = if cpu_v_empty && cpu_d_empty {
-            true // Default to true if both are empty
-        } else {
-            matches_pci_id(&chip, &config.cpu_vendor_id, &config.cpu_device_id)
-        };
-
-        // GPU PCI Vendor/Device check
-        let gpu_v_empty = config.gpu_vendor_id.is_empty();
-        let gpu_d_empty = config.gpu_device_id.is_empty();
-
-        let is_gpu_pci = if gpu_v_empty && gpu_d_empty {
-            true // Default to true if both are empty
-        } else {
-            matches_pci_id(&chip, &config.gpu_vendor_id, &config.gpu_device_id)
-        };
-
-        for feature in chip {
-            if let Ok(label) = feature.get_label() {
-                let label_lower = label.to_lowercase();
-
-                let is_cpu = (chip_name.starts_with(&config.cpu_device) || label_lower.starts_with(&config.cpu_device))
-                    && label_lower.starts_with(&config.cpu_temp_type)
-                    && is_cpu_pci;
-
-                let is_gpu = (chip_name.starts_with(&config.gpu_device) || label_lower.starts_with(&config.gpu_device))
-                    && label_lower.starts_with(&config.gpu_temp_type)
-                    && is_gpu_pci;
-
-                if is_cpu || is_gpu {
-                    for subfeature in feature {
-                        if subfeature.name().contains("input") {
-                            if is_cpu {
-                                cpu = Some(subfeature);
-                            } else if is_gpu {
-                                gpu = Some(subfeature);
-                            }
-                        }
-                    }
-                }
-            }
+// Logs the latest readings and pushes them to the display
+fn update_display<T: DisplayTransport>(
+    display: &mut Connection<T>,
+    config: &DisplayConfig,
+    cpu_temp: Option<f64>,
+    gpu_temp: Option<f64>,
+    now: Instant,
+) {
+    match (cpu_temp, gpu_temp) {
+        (Some(_cpu), Some(_gpu)) => {
+            #[cfg(debug_assertions)]
+            info!("[{}] CPU: {:.1}°C  |  GPU: {:.1}°C",
+                     get_time_string(), _cpu, _gpu);
+        },
+        (Some(_cpu), None) => {
+            #[cfg(debug_assertions)]
+            info!("[{}] CPU: {:.1}°C  |  GPU device {} not found!",
+                     get_time_string(), _cpu, config.format_device_info(DeviceType::Gpu));
+            #[cfg(not(debug_assertions))]
+            eprintln!("[{}] GPU device {} not found!",
+                      get_time_string(), config.format_device_info(DeviceType::Gpu));
+        },
+        (None, Some(_gpu)) => {
+            #[cfg(debug_assertions)]
+            info!("[{}] CPU device {} not found!  |  GPU: {:.1}°C",
+                     get_time_string(), config.format_device_info(DeviceType::Cpu), _gpu);
+            #[cfg(not(debug_assertions))]
+            eprintln!("[{}] CPU device {} not found!",
+                      get_time_string(), config.format_device_info(DeviceType::Cpu));
+        },
+        (None, None) => {
+            eprintln!("[{}] CPU device {} not found!  |  GPU device {} not found!",
+                      get_time_string(), config.format_device_info(DeviceType::Cpu), config.format_device_info(DeviceType::Gpu));
         }
     }
 
-    if cpu.is_none() {
-        if config.cpu_device.is_empty() {
-            eprintln!(
-                "Error: CPU device matching '{}' with type '{}' not found!",
-                config.cpu_device, config.cpu_temp_type
-            );
-        }
-        else {
-            eprintln!(
-                "Error: CPU device matching '{}', vendor_id '{}', device_id '{}' with type '{}' not found!",
-                config.cpu_device, config.cpu_vendor_id, config.cpu_device_id, config.cpu_temp_type
-            );
-        }
-    }
-    if gpu.is_none() {
-        if config.gpu_device.is_empty() {
-            eprintln!(
-                "Error: GPU device matching '{}' with type '{}' not found!",
-                config.gpu_device, config.gpu_temp_type
-            );
-        }
-        else {
-            eprintln!(
-                "Error: GPU device matching '{}', vendor_id '{}', device_id '{}' with type '{}' not found!",
-                config.gpu_device, config.gpu_vendor_id, config.gpu_device_id, config.gpu_temp_type
-            );
+    // Readings that don't fit after conversion are handled by the overflow setting
+    let cpu_temp = cpu_temp.map(|t| config.units.convert(t));
+    let gpu_temp = gpu_temp.map(|t| config.units.convert(t));
+    let temperatures = Temperatures::from_degrees(&cpu_temp, &gpu_temp, config.units.resolution(), config.overflow);
+    display.send_payload(&temperatures, now);
+}
+
+// Hands each attach/detach event to every panel, each of which ignores
+// events for devices other than its own
+fn handle_hotplug<H: HotplugSource, T: DisplayTransport>(source: &mut H, panels: &mut [Panel<T>], now: Instant) {
+    for event in source.poll_events() {
+        for panel in panels.iter_mut() {
+            panel.display.handle_hotplug_event(event, now);
         }
     }
-
-    (cpu, gpu)
 }
 
-fn matches_pci_id(chip: &Chip, expected_vendor: &str, expected_device: &str) -> bool {
-    let chip_name = chip.get_name().unwrap_or_default();
+fn print_missing_config_help(paths: Vec<PathBuf>) {
+    // Sample configuration template
+    const SAMPLE_CONFIG: &str = r#"# Update interval in milliseconds
+update_interval = 1000
 
-    // Extract the hex part from names like "amdgpu-pci-0300"
-    // The last part '0300' represents Bus (03) and Device/Function (00)
-    let hex_addr = match chip_name.split('-').last() {
-        Some(h) if h.len() >= 4 => h,
-        _ => return false,
-    };
+# CPU device for temperature monitoring
+[cpu]
+device = "k10temp"
+temp_type = "tctl"
 
-    // Iterate through /sys/bus/pci/devices to find a match
-    if let Ok(entries) = fs::read_dir("/sys/bus/pci/devices/") {
-        for entry in entries.flatten() {
-            let pci_id = entry.file_name().into_string().unwrap_or_default();
-
-            // Convert DBDF (0000:03:00.0) to libsensors format (0300)
-            // 0000 : [Bus] : [Device].[Function]
-            let parts: Vec<&str> = pci_id.split(|c| c == ':' || c == '.').collect();
-            if parts.len() >= 4 {
-                let bus = parts[1]; // "03"
-                let dev = parts[2]; // "00"
-                let func = parts[3]; // "0"
-
-                // libsensors "0300" = Bus(03) + Device(00) + Function(0)
-                let reconstructed = format!("{}{}{}", bus, dev, func);
-
-                // Compare against the chip's internal address (e.g., "0300")
-                if reconstructed.starts_with(hex_addr) {
-                    let path = entry.path();
-                    let vendor = fs::read_to_string(path.join("vendor")).unwrap_or_default();
-                    let device = fs::read_to_string(path.join("device")).unwrap_or_default();
-
-                    let clean_v = expected_vendor.to_lowercase();
-                    let clean_d = expected_device.to_lowercase();
-
-                    if vendor.trim().contains(&clean_v) && device.trim().contains(&clean_d) {
-                        return true;
-                    }
-                }
-            }
-        }
+# GPU device for temperature monitoring
+[gpu]
+device = "amdgpu"
+temp_type = "edge""#;
+
+    eprintln!("\nConfiguration file is missing. Please create one of the following files:");
+    for path in paths {
+        eprintln!("{}", path.display());
     }
-    false
+    eprintln!("\nUse this sample configuration as a template and adjust according to your available sensors:");
+    eprintln!("-----------------------------");
+    eprintln!("{}", SAMPLE_CONFIG);
+    eprintln!("-----------------------------");
+    eprintln!("\nRun 'antec-flux-pro-display list-sensors' to see your system's available sensors.");
+    eprintln!("\nAlternatively, run 'sudo antec-flux-pro-display setup' to pick the sensors and write the file.");
 }
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+fn main() {
+    let cli = Cli::parse_args();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+
+    let result = match cli.command() {
+        Command::Run(args) => run(&cli, &args),
+        Command::ListSensors(args) => list_sensors(&cli, &args),
+        Command::CheckConfig(args) => check_config(&cli, &args),
+        Command::Send(args) => send(&cli, &args),
+        // The setup wizard writes a config file instead of running the display
+        Command::Setup => {
+            let path = config::explicit_path(cli.config.as_deref(), |key| std::env::var_os(key))
+                .unwrap_or_else(config::system_path);
+            open_sensors(&cli).and_then(|sensors| {
+                setup::run(&*sensors, &path, &mut io::stdin().lock(), &mut io::stdout()).map_err(Into::into)
+            })
+        }
+    };
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // If --version argument is passed, print version and exit
-    let args: Vec<String> = std::env::args().collect();
-    if args.contains(&"--version".to_string()) {
-        println!("antec-flux-pro-display {}", VERSION);
-        return Ok(());
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
+}
 
-    // Initialize sensors first
-    let mut sensors = Sensors::new();
-
-    // Print available sensors
-    println!("Available temperature sensors:");
-    println!("-----------------------------");
+// Prints every chip with its temperature sensors and their current values
+fn list_sensors(cli: &Cli, args: &ListArgs) -> Result<(), Box<dyn Error>> {
+    let chips = open_sensors(cli)?.chips();
+    match args.format {
+        Format::Text => listing::print_text(&chips),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&chips)?),
+    }
+    Ok(())
+}
 
-    // List all available chips and their features
-    for chip in sensors {
-        println!("Chip: {}", chip.get_name()?);
+// The sensor backend chosen on the command line
+fn open_sensors(cli: &Cli) -> Result<Box<dyn SensorSource>, Box<dyn Error>> {
+    Ok(source::open(cli.backend, cli.sysfs_root.as_deref())?)
+}
 
-        let hwmon_path = chip.path();
-        println!("  Path: {}", hwmon_path.display());
+// Loads the config file named on the command line or found in the usual places
+fn load_config(cli: &Cli) -> Result<(PathBuf, AppConfig), Box<dyn Error>> {
+    let path = config::find_config(cli.config.as_deref(), |key| std::env::var_os(key))?;
+    let config = AppConfig::load(&path)
+        .map_err(|e| format!("Error loading configuration {}: {}", path.display(), e))?;
+    Ok((path, config))
+}
 
-        // Get the device path (e.g., /sys/class/hwmon/hwmon1/device)
-        let device_path = hwmon_path.join("device");
+// Validates the config and dry-runs sensor discovery, showing what each
+// role resolved to and why every other sensor was passed over
+fn check_config(cli: &Cli, args: &CheckArgs) -> Result<(), Box<dyn Error>> {
+    let (path, config) = load_config(cli)?;
+    for warning in &config.warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
-        // Read vendor and device IDs (stored as hex strings like "0x1002\n")
-        let vendor_hex = fs::read_to_string(device_path.join("vendor")).unwrap_or_default();
-        let device_hex = fs::read_to_string(device_path.join("device")).unwrap_or_default();
-        let vendor_trimmed = vendor_hex.trim_start_matches("0x").trim();
-        let device_trimmed = device_hex.trim_start_matches("0x").trim();
+    let sensors = open_sensors(cli)?;
+    let mut problems = Vec::new();
+    for display_config in &config.displays {
+        info!("Display [{}]: {}", display_config.name, display_config.usb);
 
-        if !vendor_trimmed.is_empty() {
-            println!("  VendorId: {}", vendor_trimmed);
-        }
+        // Every sensor the config names has to resolve, unless told to accept
+        // what the daemon does: a display with one of its two sensors
+        let mut unresolved = Vec::new();
 
-        if !device_trimmed.is_empty() {
-            println!("  DeviceId: {}", device_trimmed);
-        }
+        for (device_type, role) in [(DeviceType::Cpu, "CPU"), (DeviceType::Gpu, "GPU")] {
+            info!("  {} device: {}", role, display_config.format_device_info(device_type));
+            if SensorSpec::of(display_config, device_type).device.is_empty() {
+                continue;
+            }
 
-        println!("  Temperatures:");
-        for feature in chip {
-            if let Some(label) = feature.get_label().ok() {
-                if *feature.feature_type() == sensors::FeatureType::SENSORS_FEATURE_TEMP {
-                    if let Some(input) = feature.into_iter().find(|sf| sf.name().contains("input")) {
-                        if let Ok(temp) = input.get_value() {
-                            println!("    {}: {:.1}°C", label, temp);
-                        }
+            let candidates = discovery::explain(&*sensors, &SensorSpec::of(display_config, device_type));
+            let picked = discovery::pick(&candidates);
+            for (i, candidate) in candidates.iter().enumerate() {
+                let outcome = match &candidate.outcome {
+                    Ok(matched) => {
+                        let value = matched.value.map_or("no reading".to_string(), |v| format!("{:.1}°C", v));
+                        let status = match &picked {
+                            Ok(chosen) if *chosen == i => "using",
+                            Ok(_) => "prefix match, an exact label match wins",
+                            Err(Unresolved::Ambiguous(matches)) if matches.contains(&i) => "ambiguous",
+                            Err(_) => "prefix match, passed over for the exact label matches",
+                        };
+                        format!("{}: {} = {}", status, matched.input, value)
                     }
+                    Err(mismatch) => mismatch.to_string(),
+                };
+                info!("    {}: {}", candidate, outcome);
+            }
+
+            match picked {
+                Ok(_) => {}
+                Err(Unresolved::NotFound) => {
+                    unresolved.push(format!("no {} sensor found for display [{}]", role, display_config.name));
                 }
+                Err(Unresolved::Ambiguous(matches)) => unresolved.push(format!(
+                    "the {} sensor of display [{}] is ambiguous, it matches {}",
+                    role, display_config.name,
+                    matches.iter().map(|&i| candidates[i].to_string()).collect::<Vec<_>>().join(", ")
+                )),
             }
         }
-    }
 
-    println!("-----------------------------\n");
+        match unresolved.len() {
+            1 if args.allow_missing => eprintln!("Warning: {}, its digits will stay blank", unresolved[0]),
+            _ => problems.extend(unresolved),
+        }
+    }
 
-    // Load configuration
-    let config = match AppConfig::new() {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!("Error loading configuration: {}", e);
+    if !problems.is_empty() {
+        return Err(format!("{}: {}", path.display(), problems.join("; ")).into());
+    }
+    info!("{} is valid.", path.display());
+    Ok(())
+}
 
-            if !Path::new("/etc/antec-flux-pro-display/config.conf").exists() {
-                // Sample configuration template
-                const SAMPLE_CONFIG: &str = r#"# CPU device for temperature monitoring
-cpu_device=k10temp
-cpu_temp_type=tctl
+// Sends one frame to a display, using its USB selection, units and overflow
+// setting from the config if there is one
+fn send(cli: &Cli, args: &SendArgs) -> Result<(), Box<dyn Error>> {
+    let explicit = config::explicit_path(cli.config.as_deref(), |key| std::env::var_os(key));
+    let config = match config::find_config(cli.config.as_deref(), |key| std::env::var_os(key)) {
+        Ok(path) => Some(AppConfig::load(&path)
+            .map_err(|e| format!("Error loading configuration {}: {}", path.display(), e))?),
+        // Without any config file, the default display is used
+        Err(_) if explicit.is_none() => None,
+        Err(e) => return Err(e.into()),
+    };
+    let display_config = match (&config, &args.display) {
+        (Some(config), Some(name)) => Some(config.displays.iter()
+            .find(|d| &d.name == name)
+            .ok_or_else(|| format!("No display named [{}] in the config", name))?),
+        (Some(config), None) => config.displays.first(),
+        (None, Some(name)) => return Err(format!("No display named [{}], there is no config file", name).into()),
+        (None, None) => None,
+    };
 
-# GPU device for temperature monitoring
-gpu_device=amdgpu
-gpu_temp_type=edge
-
-# Update interval in milliseconds
-update_interval=1000"#;
-
-                eprintln!("\nConfiguration file is missing. Please create the following file:");
-                eprintln!("/etc/antec-flux-pro-display/config.conf");
-                eprintln!("\nUUse this sample configuration as a template and adjust according to your available sensors above:");
-                eprintln!("-----------------------------");
-                eprintln!("{}", SAMPLE_CONFIG);
-                eprintln!("-----------------------------");
-                eprintln!("\nMake sure to adjust the values according to your system's available sensors shown above.");
+    let frame = match &args.raw {
+        Some(hex) => {
+            let bytes = cli::parse_hex_frame(hex)?;
+            let invalid = |e| format!("Not sending invalid frame: {}", e);
+            let frame = Frame::decode(&bytes).map_err(invalid)?;
+            // Other commands go through as they are, for trying them out,
+            // but a temperature update has to hold digits the panel can show
+            match Temperatures::from_frame(&frame) {
+                Ok(_) | Err(DecodeError::UnexpectedCommand { .. }) => {}
+                Err(e) => return Err(invalid(e).into()),
             }
-
-            std::process::exit(1);
+            bytes
+        }
+        None => {
+            let units = display_config.map(|d| d.units).unwrap_or_default();
+            let overflow = display_config.map(|d| d.overflow).unwrap_or_default();
+            let cpu = cli::parse_temperature(args.cpu.as_deref().unwrap_or("-"))?.map(|t| units.convert(t));
+            let gpu = cli::parse_temperature(args.gpu.as_deref().unwrap_or("-"))?.map(|t| units.convert(t));
+            Temperatures::from_degrees(&cpu, &gpu, units.resolution(), overflow).to_frame().encode()
         }
     };
 
-    // Print initial information
-    println!("Starting temperature monitor...");
-    println!("CPU device: {}", config.format_device_info(DeviceType::Cpu));
-    println!("GPU device: {}", config.format_device_info(DeviceType::Gpu));
-    println!("Update interval: {}ms\n", config.update_interval);
-
-    let device = UsbDevice::open(usb::VENDOR_ID, usb::PRODUCT_ID)?;
-
-    // Need to claim the interface to continue.
-    device.claim_interface();
+    let target = display_config.map(|d| d.usb.clone()).unwrap_or_default();
+    let mut device = UsbDevice::open(&target)?;
+    device.claim_interface()?;
+    device.send_frame(&frame)?;
+    info!("Sent {} to display {}.", frame.iter().map(|b| format!("{:02x}", b)).collect::<String>(), target);
+    Ok(())
+}
 
-    let (mut cpu_feature, mut gpu_feature) = discover_features(&sensors, &config);
+// Connects every panel. With `wait` set, displays that aren't plugged in yet
+// are waited for, the timeout counting for all of them together.
+fn connect_panels<T: DisplayTransport>(panels: &mut [Panel<T>], wait: Option<Option<Duration>>) -> Result<(), UsbError> {
+    let start = Instant::now();
+    for panel in panels {
+        match wait {
+            Some(timeout) => {
+                let remaining = timeout.map(|timeout| timeout.saturating_sub(start.elapsed()));
+                panel.display.wait_for_device(remaining, connection::WAIT_POLL_INTERVAL)?;
+            }
+            None => panel.display.connect()?,
+        }
+    }
+    Ok(())
+}
 
-    if cpu_feature.is_none() && gpu_feature.is_none() {
-        println!("Both CPU and GPU devices were not found. Please check your config or run 'sensors' in terminal to see available names. Program exiting.");
-        std::process::exit(1);
+// Says once that a lost sensor may not come back with this backend, and how
+// to get one that picks it up again
+fn warn_no_rescan(sensors: &dyn SensorSource, warned: &mut bool) {
+    if sensors.rescans() || *warned {
+        return;
     }
+    eprintln!("[{}] Warning: libsensors only sees the chips found at startup. If the sensors don't come back, \
+               restart the service or run with --backend sysfs, which finds chips again after a driver reload or resume.",
+              get_time_string());
+    *warned = true;
+}
 
-    loop {
-        let start_time = Instant::now();
+// Looks for a panel's sensors again and claims its display once more, as
+// needed after a driver reload or a resume from suspend
+fn refresh_panel<T: DisplayTransport>(panel: &mut Panel<T>, sensors: &dyn SensorSource, rescan_warned: &mut bool, now: Instant) {
+    panel.discover_features(sensors);
+    if !panel.has_sensors() {
+        warn_no_rescan(sensors, rescan_warned);
+    }
+    panel.display.reclaim(now);
+}
 
-        // Attempt to read temperatures
-        let cpu_temp = cpu_feature.as_ref().and_then(|f| f.get_value().ok());
-        let gpu_temp = gpu_feature.as_ref().and_then(|f| f.get_value().ok());
+// One pass of the main loop: applies the hotplug events, then shows every
+// panel's readings. A panel whose sensors are all gone is refreshed first.
+fn tick<T: DisplayTransport>(
+    panels: &mut [Panel<T>],
+    sensors: &dyn SensorSource,
+    hotplug: &mut impl HotplugSource,
+    rescan_warned: &mut bool,
+    now: Instant,
+) {
+    handle_hotplug(hotplug, panels, now);
+
+    for panel in panels {
+        let read = |panel: &Panel<T>| (
+            panel.cpu_feature.as_ref().and_then(|f| sensors.read(f)),
+            panel.gpu_feature.as_ref().and_then(|f| sensors.read(f)),
+        );
+        let (mut cpu_temp, mut gpu_temp) = read(panel);
 
         // Handle missing sensors (e.g., driver unloaded/reloaded during sleep)
         if cpu_temp.is_none() && gpu_temp.is_none() {
@@ -385,43 +432,145 @@ update_interval=1000"#;
             eprintln!("[{}] Sensors lost. Attempting re-discovery...",
                       get_time_string());
 
-            sensors = Sensors::new(); // Refresh libsensors internal state
-            let (c, g) = discover_features(&sensors, &config);
-            cpu_feature = c;
-            gpu_feature = g;
+            refresh_panel(panel, sensors, rescan_warned, now);
+            (cpu_temp, gpu_temp) = read(panel);
+        }
+
+        update_display(&mut panel.display, &panel.config, cpu_temp, gpu_temp, now);
+    }
+}
 
-            device.claim_interface();
+// Drives the displays until the process is stopped
+fn run(cli: &Cli, args: &RunArgs) -> Result<(), Box<dyn Error>> {
+    let wait_for_device = args.wait_for_device.map(|secs| secs.map(Duration::from_secs));
+    let cli_config_path = cli.config.clone();
+    let sensors = open_sensors(cli)?;
+
+    // Load configuration, picking known sensors if there's no config file at all
+    let explicit_config_path = config::explicit_path(cli_config_path.as_deref(), |key| std::env::var_os(key));
+    let (mut config_path, mut config) = match config::find_config(cli_config_path.as_deref(), |key| std::env::var_os(key)) {
+        Ok(path) => match AppConfig::load(&path) {
+            Ok(config) => (Some(path), config),
+            Err(e) => return Err(format!("Error loading configuration {}: {}", path.display(), e).into()),
+        },
+        Err(e) if explicit_config_path.is_some() => {
+            print_missing_config_help(explicit_config_path.into_iter().collect());
+            return Err(format!("Error loading configuration: {}", e).into());
         }
+        Err(e) => match autodetect::detect(&*sensors) {
+            Ok(config) => {
+                info!("{}. Using automatically detected sensors, run 'sudo antec-flux-pro-display setup' to choose others.", e);
+                (None, config)
+            }
+            Err(detect_error) => {
+                print_missing_config_help(config::search_paths(|key| std::env::var_os(key)).into_iter()
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                    .collect());
+                return Err(format!("Error loading configuration: {}\n{}", e, detect_error).into());
+            }
+        },
+    };
+    for warning in &config.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    // Print initial information
+    info!("Starting temperature monitor...");
+    if let Some(path) = &config_path {
+        info!("Config file: {}", path.display());
+    }
+    for display_config in &config.displays {
+        if config.displays.len() > 1 {
+            info!("Display [{}]: {}", display_config.name, display_config.usb);
+        }
+        info!("CPU device: {}", display_config.format_device_info(DeviceType::Cpu));
+        info!("GPU device: {}", display_config.format_device_info(DeviceType::Gpu));
+    }
+    info!("Update interval: {}ms\n", config.update_interval);
+
+    let mut panels: Vec<Panel<UsbDevice>> = config.displays.iter().map(Panel::new).collect();
+
+    // Opening also claims the interface, which is needed to continue.
+    connect_panels(&mut panels, wait_for_device)?;
+
+    // Without hotplug support, a lost display is only noticed when a write fails
+    let mut hotplug = match UsbHotplug::register(&usb_ids(&config)) {
+        Ok(hotplug) => hotplug,
+        Err(e) => {
+            eprintln!("Could not watch for USB hotplug events: {}", e);
+            None
+        }
+    };
+
+    for panel in &mut panels {
+        panel.discover_features(&*sensors);
+        if !panel.has_sensors() {
+            return Err("Both CPU and GPU devices were not found. Please check your config or run 'antec-flux-pro-display list-sensors' to see available names. Program exiting.".into());
+        }
+    }
 
-        match (cpu_temp, gpu_temp) {
-            (Some(_cpu), Some(_gpu)) => {
-                #[cfg(debug_assertions)]
-                println!("[{}] CPU: {:.1}°C  |  GPU: {:.1}°C",
-                         get_time_string(), _cpu, _gpu);
-            },
-            (Some(_cpu), None) => {
-                #[cfg(debug_assertions)]
-                println!("[{}] CPU: {:.1}°C  |  GPU device {} not found!",
-                         get_time_string(), _cpu, config.format_device_info(DeviceType::Gpu));
-                #[cfg(not(debug_assertions))]
-                eprintln!("[{}] GPU device {} not found!",
-                          get_time_string(), config.format_device_info(DeviceType::Gpu));
-            },
-            (None, Some(_gpu)) => {
-                #[cfg(debug_assertions)]
-                println!("[{}] CPU device {} not found!  |  GPU: {:.1}°C",
-                         get_time_string(), config.format_device_info(DeviceType::Cpu), _gpu);
-                #[cfg(not(debug_assertions))]
-                eprintln!("[{}] CPU device {} not found!",
-                          get_time_string(), config.format_device_info(DeviceType::Cpu));
-            },
-            (None, None) => {
-                eprintln!("[{}] CPU device {} not found!  |  GPU device {} not found!",
-                          get_time_string(), config.format_device_info(DeviceType::Cpu), config.format_device_info(DeviceType::Gpu));
+    // Without a config file, every place one is looked for is watched, so
+    // that one written later takes over. The system-wide folder, where setup
+    // writes it, is created if missing, while others that are missing are
+    // skipped.
+    let watched = match &config_path {
+        Some(path) => vec![path.clone()],
+        None => {
+            if let Some(dir) = config::system_path().parent() {
+                fs::create_dir_all(dir).ok();
             }
+            config::search_paths(|key| std::env::var_os(key))
         }
+    };
+    let mut watcher = match ConfigWatcher::new(&watched) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("Could not set up config reloading: {}", e);
+            None
+        }
+    };
 
-        device.send_payload(&cpu_temp, &gpu_temp);
+    let mut rescan_warned = false;
+    loop {
+        let start_time = Instant::now();
+
+        if watcher.as_mut().is_some_and(|w| w.reload_requested()) {
+            let path = config_path.clone()
+                .or_else(|| config::find_config(cli_config_path.as_deref(), |key| std::env::var_os(key)).ok());
+            match path.map(|path| (AppConfig::load(&path), path)) {
+                None => eprintln!("[{}] Not reloading: no configuration file found. Keeping the automatically detected sensors.",
+                                  get_time_string()),
+                Some((Ok(new_config), path)) => {
+                    // Found once, so a missing sensor is only reported once
+                    let features: Vec<_> = new_config.displays.iter().map(|d| discover_features(&*sensors, d)).collect();
+                    if features.iter().any(|(cpu, gpu)| cpu.is_none() && gpu.is_none()) {
+                        eprintln!("[{}] Not reloading {}: a display has neither its CPU nor its GPU sensor. Keeping the current configuration.",
+                                  get_time_string(), path.display());
+                    } else {
+                        for warning in &new_config.warnings {
+                            eprintln!("[{}] Warning: {}", get_time_string(), warning);
+                        }
+                        if usb_ids(&new_config) != usb_ids(&config) {
+                            hotplug = UsbHotplug::register(&usb_ids(&new_config)).unwrap_or_else(|e| {
+                                eprintln!("[{}] Could not watch for USB hotplug events: {}", get_time_string(), e);
+                                None
+                            });
+                        }
+                        panels = rebuild_panels(panels, &new_config.displays);
+                        for (panel, features) in panels.iter_mut().zip(features) {
+                            panel.set_features(features);
+                        }
+                        config = new_config;
+                        info!("[{}] Configuration reloaded from {}.", get_time_string(), path.display());
+                        config_path = Some(path);
+                    }
+                }
+                Some((Err(e), path)) => eprintln!("[{}] Not reloading {}: {}. Keeping the current configuration.",
+                                                  get_time_string(), path.display(), e),
+            }
+        }
+
+        tick(&mut panels, &*sensors, &mut hotplug, &mut rescan_warned, Instant::now());
 
         // Sleep and Detect Wake-up
         let interval = Duration::from_millis(config.update_interval);
@@ -430,15 +579,197 @@ update_interval=1000"#;
         // If we slept for much longer than intended, assume the PC was suspended
         if start_time.elapsed() > interval + Duration::from_secs(2) {
             #[cfg(debug_assertions)]
-            println!("[{}] Wake-up detected. Refreshing hardware handles...",
+            info!("[{}] Wake-up detected. Refreshing hardware handles...",
                      get_time_string());
 
-            sensors = Sensors::new();
-            let (c, g) = discover_features(&sensors, &config);
-            cpu_feature = c;
-            gpu_feature = g;
+            for panel in &mut panels {
+                refresh_panel(panel, &*sensors, &mut rescan_warned, Instant::now());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hotplug::HotplugEvent;
+    use hotplug::mock::MockHotplug;
+    use listing::{ChipInfo, SensorInfo};
+    use protocol::Reading;
+    use source::mock::MockSource;
+    use transport::mock::MockTransport;
+
+    fn test_config() -> DisplayConfig {
+        let config = AppConfig::parse_legacy("cpu_device=k10temp\ncpu_temp_type=tctl\ngpu_device=amdgpu\ngpu_temp_type=edge\n");
+        config.unwrap().displays.remove(0)
+    }
+
+    fn chip(name: &str, path: &str, label: &str, value: f64) -> ChipInfo {
+        let mut chip = ChipInfo::new(name.to_string(), name.split('-').next().unwrap().to_string(), path.into());
+        chip.temperatures.push(SensorInfo {
+            label: label.to_string(),
+            feature: "temp1".to_string(),
+            input: Some(value),
+            min: None,
+            max: None,
+            crit: None,
+            subfeatures: vec!["temp1_input".to_string()],
+        });
+        chip
+    }
+
+    fn connected_panel(sensors: &MockSource) -> Panel<MockTransport> {
+        let mut panel = Panel::new(&test_config());
+        panel.display.connect().unwrap();
+        panel.discover_features(sensors);
+        panel
+    }
+
+    #[test]
+    fn test_update_display_sends_frame_per_reading() {
+        let config = test_config();
+        let mut display = Connection::<MockTransport>::new(config.usb.clone());
+        display.connect().unwrap();
+
+        let now = Instant::now();
+        update_display(&mut display, &config, Some(24.0), Some(16.0), now);
+        update_display(&mut display, &config, None, Some(16.0), now);
+        update_display(&mut display, &config, None, None, now);
+
+        assert_eq!(
+            vec![
+                vec![85, 170, 1, 1, 6, 2, 4, 0, 1, 6, 0, 20],
+                protocol::generate_payload(&None, &Some(16.0)),
+                protocol::generate_payload(&None, &None),
+            ],
+            display.device().unwrap().frames
+        );
+    }
+
+    #[test]
+    fn test_rebuild_panels_keeps_unchanged_connections() {
+        let mut top = test_config();
+        top.usb.serial = Some("A1".to_string());
+        let mut front = test_config();
+        front.usb.serial = Some("B2".to_string());
+        let mut panels: Vec<Panel<MockTransport>> = vec![Panel::new(&top), Panel::new(&front)];
+        for panel in &mut panels {
+            panel.display.connect().unwrap();
+        }
+        let top_location = panels[0].display.device().unwrap().location();
+
+        // The top display shows another sensor, the front one moves to a new serial.
+        top.gpu_temp_type = "junction".to_string();
+        front.usb.serial = Some("C3".to_string());
+        let panels = rebuild_panels(panels, &[front.clone(), top]);
 
-            device.claim_interface();
+        let [new_front, new_top] = &panels[..] else {
+            panic!("expected two panels");
+        };
+        assert_eq!("junction", new_top.config.gpu_temp_type);
+        assert_eq!(top_location, new_top.display.device().unwrap().location());
+        assert_eq!(front.usb, new_front.config.usb);
+        assert!(!new_front.display.is_connected());
+    }
+
+    #[test]
+    fn test_handle_hotplug_reaches_every_panel() {
+        let config = test_config();
+        let mut panels: Vec<Panel<MockTransport>> = vec![Panel::new(&config), Panel::new(&config)];
+        for panel in &mut panels {
+            panel.display.connect().unwrap();
         }
+        let location = panels[1].display.device().unwrap().location().unwrap();
+        let mut hotplug = MockHotplug::default();
+        hotplug.batches.push_back(vec![HotplugEvent::Left(location)]);
+
+        handle_hotplug(&mut hotplug, &mut panels, Instant::now());
+
+        assert!(panels[0].display.is_connected());
+        assert!(!panels[1].display.is_connected());
+    }
+
+    #[test]
+    fn test_update_display_shows_fahrenheit_in_whole_degrees() {
+        let mut config = test_config();
+        config.units = config::Units::Fahrenheit;
+        let mut display = Connection::<MockTransport>::new(config.usb.clone());
+        display.connect().unwrap();
+
+        // 65°C is 149°F, shown as the digits 1 4 9
+        update_display(&mut display, &config, Some(65.0), None, Instant::now());
+
+        let frame = Frame::decode(&display.device().unwrap().frames[0]).unwrap();
+        let shown = Temperatures::from_frame(&frame).unwrap();
+        assert_eq!(Reading::Digits([1, 4, 9]), shown.cpu);
+        assert_eq!(Reading::Missing, shown.gpu);
+    }
+
+    #[test]
+    fn test_tick_follows_the_display_being_replugged() {
+        let sensors = MockSource {
+            chips: vec![chip("k10temp-pci-00c3", "/hwmon/hwmon1", "Tctl", 50.0)],
+            ..Default::default()
+        };
+        let mut panels = [connected_panel(&sensors)];
+        let location = panels[0].display.device().unwrap().location().unwrap();
+        let mut hotplug = MockHotplug::default();
+        hotplug.batches.extend([vec![], vec![HotplugEvent::Left(location)], vec![HotplugEvent::Arrived(location)]]);
+        let mut rescan_warned = false;
+        let now = Instant::now();
+
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+        assert_eq!(vec![protocol::generate_payload(&Some(50.0), &None)], panels[0].display.device().unwrap().frames);
+
+        // Unplugged, the frame is dropped until the display is back
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+        assert!(!panels[0].display.is_connected());
+
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+        let device = panels[0].display.device().unwrap();
+        assert_ne!(Some(location), device.location());
+        assert_eq!((1, 1), (device.claims, device.frames.len()));
+    }
+
+    #[test]
+    fn test_tick_finds_lost_sensors_again() {
+        let mut sensors = MockSource {
+            chips: vec![
+                chip("k10temp-pci-00c3", "/hwmon/hwmon1", "Tctl", 50.0),
+                chip("amdgpu-pci-0300", "/hwmon/hwmon2", "edge", 40.0),
+            ],
+            ..Default::default()
+        };
+        let mut panels = [connected_panel(&sensors)];
+        let mut hotplug = MockHotplug::default();
+        let mut rescan_warned = false;
+        let now = Instant::now();
+
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+
+        // Both drivers go away, e.g. during suspend, so the digits go blank
+        let chips = std::mem::take(&mut sensors.chips);
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+        assert!(!panels[0].has_sensors());
+
+        // They come back under new hwmon numbers, are found again and the
+        // display is claimed again before it's updated
+        sensors.chips = chips;
+        sensors.chips[0].path = "/hwmon/hwmon3".into();
+        sensors.chips[1].path = "/hwmon/hwmon4".into();
+        tick(&mut panels, &sensors, &mut hotplug, &mut rescan_warned, now);
+
+        let device = panels[0].display.device().unwrap();
+        assert_eq!(
+            vec![
+                protocol::generate_payload(&Some(50.0), &Some(40.0)),
+                protocol::generate_payload(&None, &None),
+                protocol::generate_payload(&Some(50.0), &Some(40.0)),
+            ],
+            device.frames
+        );
+        assert_eq!(3, device.claims);
+        assert_eq!(Some(SensorRef { chip: "/hwmon/hwmon4".into(), feature: "temp1".to_string() }), panels[0].gpu_feature);
+        assert!(!rescan_warned);
     }
 }
@@ -0,0 +1,378 @@
+//! Wire format of the messages sent to the case display.
+//!
+//! Every frame looks like this:
+//!
+//! ```text
+//! 55 AA | command | subcommand | length | data[length] | checksum
+//! ```
+//!
+//! where `checksum` is the wrapping sum of all preceding bytes. The only
+//! message the panel is known to accept is the temperature update, whose six
+//! data bytes are two [`Reading`]s: CPU first, then GPU.
+
+use std::fmt;
+
+pub const HEADER: [u8; 2] = [0x55, 0xAA];
+
+pub const TEMPERATURE_COMMAND: u8 = 1;
+pub const TEMPERATURE_SUBCOMMAND: u8 = 1;
+
+// Bytes sent in place of each digit when there is no reading
+const MISSING: u8 = 238;
+
+// The length is a single byte
+pub const MAX_DATA_LEN: usize = u8::MAX as usize;
+
+// Largest value the three digits can show, in units of the resolution
+const MAX_DIGITS: f64 = 999.0;
+
+/// How much of a degree the last of the three digits stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resolution {
+    /// 00.0 to 99.9, enough for Celsius
+    #[default]
+    Tenths,
+    /// 0 to 999, for Fahrenheit, where normal temperatures are above 100
+    Whole,
+}
+
+/// What to show for temperatures that don't fit in the panel's three digits,
+/// above 99.9 or 999 depending on the resolution. The panel has no known
+/// codes for letters, so a literal "HI" can't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Show the largest value, 99.9 or 999.
+    #[default]
+    Clamp,
+    /// Leave the digits blank, the same as a missing sensor.
+    Blank,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    command: u8,
+    subcommand: u8,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    TooShort,
+    BadHeader,
+    LengthMismatch { declared: usize, actual: usize },
+    BadChecksum { expected: u8, actual: u8 },
+    UnexpectedCommand { command: u8, subcommand: u8 },
+    InvalidDigit(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooShort => write!(f, "frame is too short"),
+            DecodeError::BadHeader => write!(f, "frame does not start with 55 AA"),
+            DecodeError::LengthMismatch { declared, actual } => {
+                write!(f, "frame declares {} data bytes but has {}", declared, actual)
+            }
+            DecodeError::BadChecksum { expected, actual } => {
+                write!(f, "checksum is {:#04x}, expected {:#04x}", actual, expected)
+            }
+            DecodeError::UnexpectedCommand { command, subcommand } => {
+                write!(f, "unexpected command {}/{}", command, subcommand)
+            }
+            DecodeError::InvalidDigit(digit) => write!(f, "invalid digit {}", digit),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |acc, e| acc.wrapping_add(*e))
+}
+
+impl Frame {
+    /// Panics if `data` is longer than [`MAX_DATA_LEN`], which the length
+    /// byte can't describe.
+    pub fn new(command: u8, subcommand: u8, data: Vec<u8>) -> Self {
+        assert!(data.len() <= MAX_DATA_LEN, "frame data is {} bytes, at most {} fit", data.len(), MAX_DATA_LEN);
+        Frame { command, subcommand, data }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = HEADER.to_vec();
+        bytes.push(self.command);
+        bytes.push(self.subcommand);
+        bytes.push(self.data.len() as u8);
+        bytes.extend_from_slice(&self.data);
+        bytes.push(checksum(&bytes));
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        // Header, command, subcommand, length and checksum
+        if bytes.len() < HEADER.len() + 4 {
+            return Err(DecodeError::TooShort);
+        }
+        if bytes[..2] != HEADER {
+            return Err(DecodeError::BadHeader);
+        }
+
+        let (body, sum) = bytes.split_at(bytes.len() - 1);
+        let data = &body[5..];
+        let declared = body[4] as usize;
+        if declared != data.len() {
+            return Err(DecodeError::LengthMismatch { declared, actual: data.len() });
+        }
+
+        let expected = checksum(body);
+        if sum[0] != expected {
+            return Err(DecodeError::BadChecksum { expected, actual: sum[0] });
+        }
+
+        Ok(Frame::new(body[2], body[3], data.to_vec()))
+    }
+}
+
+/// One temperature as shown on the panel: three digits, or blank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reading {
+    /// Left to right: tens, ones and tenths of a degree, or hundreds, tens
+    /// and ones with [`Resolution::Whole`].
+    Digits([u8; 3]),
+    Missing,
+}
+
+impl Reading {
+    pub fn from_degrees(temp: &Option<f64>, resolution: Resolution, overflow: Overflow) -> Self {
+        match temp {
+            Some(temp) => encode_temperature(*temp, resolution, overflow),
+            None => Reading::Missing,
+        }
+    }
+
+    fn encode(&self) -> [u8; 3] {
+        match *self {
+            Reading::Digits(digits) => digits,
+            Reading::Missing => [MISSING; 3],
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes == [MISSING; 3] {
+            return Ok(Reading::Missing);
+        }
+        if let Some(&digit) = bytes.iter().find(|&&b| b > 9) {
+            return Err(DecodeError::InvalidDigit(digit));
+        }
+        Ok(Reading::Digits([bytes[0], bytes[1], bytes[2]]))
+    }
+}
+
+/// The temperature update message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Temperatures {
+    pub cpu: Reading,
+    pub gpu: Reading,
+}
+
+impl Temperatures {
+    pub fn from_degrees(cpu_temp: &Option<f64>, gpu_temp: &Option<f64>, resolution: Resolution, overflow: Overflow) -> Self {
+        Temperatures {
+            cpu: Reading::from_degrees(cpu_temp, resolution, overflow),
+            gpu: Reading::from_degrees(gpu_temp, resolution, overflow),
+        }
+    }
+
+    pub fn to_frame(self) -> Frame {
+        let mut data = self.cpu.encode().to_vec();
+        data.extend_from_slice(&self.gpu.encode());
+        Frame::new(TEMPERATURE_COMMAND, TEMPERATURE_SUBCOMMAND, data)
+    }
+
+    pub fn from_frame(frame: &Frame) -> Result<Self, DecodeError> {
+        if (frame.command, frame.subcommand) != (TEMPERATURE_COMMAND, TEMPERATURE_SUBCOMMAND) {
+            return Err(DecodeError::UnexpectedCommand {
+                command: frame.command,
+                subcommand: frame.subcommand,
+            });
+        }
+        if frame.data.len() != 6 {
+            return Err(DecodeError::LengthMismatch { declared: 6, actual: frame.data.len() });
+        }
+        Ok(Temperatures {
+            cpu: Reading::decode(&frame.data[..3])?,
+            gpu: Reading::decode(&frame.data[3..])?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub fn generate_payload(cpu_temp: &Option<f64>, gpu_temp: &Option<f64>) -> Vec<u8> {
+    Temperatures::from_degrees(cpu_temp, gpu_temp, Resolution::default(), Overflow::default())
+        .to_frame()
+        .encode()
+}
+
+// Rounds to the nearest tenth or whole degree. NaN has no meaningful digits
+// and is shown as missing, negative values (including -inf) show as zero,
+// and anything that rounds above 99.9 or 999 (including +inf) is handled
+// according to `overflow`.
+fn encode_temperature(temp: f64, resolution: Resolution, overflow: Overflow) -> Reading {
+    if temp.is_nan() {
+        return Reading::Missing;
+    }
+
+    let scale = match resolution {
+        Resolution::Tenths => 10.0,
+        Resolution::Whole => 1.0,
+    };
+    let value = (temp * scale).round().max(0.0);
+    if value > MAX_DIGITS {
+        return match overflow {
+            Overflow::Clamp => Reading::Digits([9, 9, 9]),
+            Overflow::Blank => Reading::Missing,
+        };
+    }
+
+    let value = value as u16;
+    Reading::Digits([(value / 100) as u8, (value / 10 % 10) as u8, (value % 10) as u8])
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_generate_payload() {
+        let actual = generate_payload(&Some(24.0), &Some(16.0));
+        let expected = vec![85, 170, 1, 1, 6, 2, 4, 0, 1, 6, 0, 20];
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_generate_payload_with_no_gpu() {
+        let actual = generate_payload(&Some(24.0), &None);
+        let expected = vec![85, 170, 1, 1, 6, 2, 4, 0, 238, 238, 238, 215];
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_temperatures_round_trip() {
+        let temperatures = Temperatures {
+            cpu: Reading::Digits([4, 7, 5]),
+            gpu: Reading::Missing,
+        };
+
+        let frame = Frame::decode(&temperatures.to_frame().encode()).unwrap();
+
+        assert_eq!(Ok(temperatures), Temperatures::from_frame(&frame));
+    }
+
+    #[test]
+    fn test_frame_round_trip_other_command() {
+        let frame = Frame::new(2, 7, vec![1, 2, 3]);
+        assert_eq!(Ok(frame.clone()), Frame::decode(&frame.encode()));
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_frames() {
+        let bytes = generate_payload(&Some(24.0), &Some(16.0));
+
+        assert_eq!(Err(DecodeError::TooShort), Frame::decode(&bytes[..5]));
+
+        let mut bad_header = bytes.clone();
+        bad_header[0] = 0;
+        assert_eq!(Err(DecodeError::BadHeader), Frame::decode(&bad_header));
+
+        let mut bad_length = bytes.clone();
+        bad_length[4] = 5;
+        assert_eq!(
+            Err(DecodeError::LengthMismatch { declared: 5, actual: 6 }),
+            Frame::decode(&bad_length)
+        );
+
+        let mut bad_checksum = bytes.clone();
+        bad_checksum[11] = 0;
+        assert_eq!(
+            Err(DecodeError::BadChecksum { expected: 20, actual: 0 }),
+            Frame::decode(&bad_checksum)
+        );
+    }
+
+    #[test]
+    fn test_frame_data_limit() {
+        let frame = Frame::new(2, 7, vec![0; MAX_DATA_LEN]);
+        assert_eq!(Ok(frame.clone()), Frame::decode(&frame.encode()));
+        assert!(std::panic::catch_unwind(|| Frame::new(2, 7, vec![0; MAX_DATA_LEN + 1])).is_err());
+    }
+
+    #[test]
+    fn test_from_frame_rejects_other_commands() {
+        let frame = Frame::new(2, 1, vec![0; 6]);
+        assert_eq!(
+            Err(DecodeError::UnexpectedCommand { command: 2, subcommand: 1 }),
+            Temperatures::from_frame(&frame)
+        );
+    }
+
+    #[test]
+    fn test_encode_temperature_rounds_tenths() {
+        assert_eq!(Reading::Digits([4, 5, 7]), encode_temperature(45.66, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(Reading::Digits([5, 0, 0]), encode_temperature(49.96, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(Reading::Digits([0, 7, 3]), encode_temperature(7.3, Resolution::Tenths, Overflow::Clamp));
+    }
+
+    #[test]
+    fn test_encode_temperature_out_of_range() {
+        let zero = Reading::Digits([0, 0, 0]);
+        let max = Reading::Digits([9, 9, 9]);
+
+        assert_eq!(zero, encode_temperature(-12.5, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(zero, encode_temperature(f64::NEG_INFINITY, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(Reading::Missing, encode_temperature(f64::NAN, Resolution::Tenths, Overflow::Clamp));
+
+        assert_eq!(max, encode_temperature(99.94, Resolution::Tenths, Overflow::Blank));
+        assert_eq!(max, encode_temperature(99.96, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(max, encode_temperature(250.0, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(max, encode_temperature(f64::INFINITY, Resolution::Tenths, Overflow::Clamp));
+        assert_eq!(Reading::Missing, encode_temperature(99.96, Resolution::Tenths, Overflow::Blank));
+        assert_eq!(Reading::Missing, encode_temperature(f64::INFINITY, Resolution::Tenths, Overflow::Blank));
+    }
+
+    #[test]
+    fn test_encode_temperature_whole_degrees() {
+        assert_eq!(Reading::Digits([1, 4, 9]), encode_temperature(149.0, Resolution::Whole, Overflow::Clamp));
+        assert_eq!(Reading::Digits([0, 8, 7]), encode_temperature(86.5, Resolution::Whole, Overflow::Clamp));
+        assert_eq!(Reading::Digits([9, 9, 9]), encode_temperature(1200.0, Resolution::Whole, Overflow::Clamp));
+        assert_eq!(Reading::Missing, encode_temperature(999.5, Resolution::Whole, Overflow::Blank));
+    }
+
+    fn overflow() -> impl Strategy<Value = Overflow> {
+        prop_oneof![Just(Overflow::Clamp), Just(Overflow::Blank)]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_every_frame_decodes(
+            cpu in proptest::option::of(any::<f64>()),
+            gpu in proptest::option::of(any::<f64>()),
+            resolution in prop_oneof![Just(Resolution::Tenths), Just(Resolution::Whole)],
+            overflow in overflow(),
+        ) {
+            let temperatures = Temperatures::from_degrees(&cpu, &gpu, resolution, overflow);
+            let frame = Frame::decode(&temperatures.to_frame().encode()).unwrap();
+            prop_assert_eq!(Ok(temperatures), Temperatures::from_frame(&frame));
+        }
+
+        #[test]
+        fn prop_in_range_values_round_to_nearest_tenth(temp in 0.0..99.94f64) {
+            let Reading::Digits([tens, ones, tenths]) = encode_temperature(temp, Resolution::Tenths, Overflow::Clamp) else {
+                panic!("{} encoded as missing", temp);
+            };
+            let shown = f64::from(tens) * 10.0 + f64::from(ones) + f64::from(tenths) / 10.0;
+            prop_assert!((shown - temp).abs() <= 0.05 + 1e-9, "{} shown as {}", temp, shown);
+        }
+    }
+}
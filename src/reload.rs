@@ -0,0 +1,134 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use inotify::{Event, Inotify, WatchDescriptor, WatchMask};
+use signal_hook::consts::SIGHUP;
+
+use crate::get_time_string;
+
+/// Tells the main loop when to read the config file again: after a SIGHUP,
+/// or when one of the watched files is written, replaced or created.
+pub struct ConfigWatcher {
+    hangup: Arc<AtomicBool>,
+    inotify: Option<Inotify>,
+    files: Vec<(WatchDescriptor, OsString)>,
+}
+
+impl ConfigWatcher {
+    /// Installs the SIGHUP handler and starts watching `paths`. Files in
+    /// directories that don't exist are skipped. Failing to watch a file only
+    /// disables reloading on its change, not on SIGHUP.
+    pub fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        let hangup = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGHUP, Arc::clone(&hangup))?;
+
+        let mut inotify = match Inotify::init() {
+            Ok(inotify) => Some(inotify),
+            Err(e) => {
+                eprintln!("Could not watch the config file for changes, reload it with SIGHUP instead: {}", e);
+                None
+            }
+        };
+        let mut files = Vec::new();
+        for path in paths {
+            // Editors often save by replacing the file, which would end a watch
+            // on the file itself, so watch its directory and filter by name
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let (Some(watcher), Some(file_name)) = (&mut inotify, path.file_name()) else {
+                continue;
+            };
+            match watcher.watches().add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE) {
+                Ok(wd) => files.push((wd, file_name.to_os_string())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => eprintln!("Could not watch {} for changes, reload it with SIGHUP instead: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self { hangup, inotify, files })
+    }
+
+    /// Returns whether a reload was requested since the last call, without
+    /// blocking.
+    pub fn reload_requested(&mut self) -> bool {
+        let mut requested = self.hangup.swap(false, Ordering::SeqCst);
+
+        let Some(inotify) = &mut self.inotify else {
+            return requested;
+        };
+        let mut buffer = [0; 4096];
+        loop {
+            match inotify.read_events(&mut buffer) {
+                Ok(mut events) => {
+                    let watched = |event: &Event<&OsStr>| {
+                        self.files.iter().any(|(wd, file_name)| *wd == event.wd && event.name == Some(file_name.as_os_str()))
+                    };
+                    if events.any(|event| watched(&event)) {
+                        requested = true;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("[{}] Stopped watching the config file: {}", get_time_string(), e);
+                    self.inotify = None;
+                    break;
+                }
+            }
+        }
+        requested
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::fixture::TempDir;
+
+    #[test]
+    fn test_reload_requested() {
+        let dir = TempDir::new("reload");
+        let path = dir.write("config.toml", "");
+        let mut watcher = ConfigWatcher::new(std::slice::from_ref(&path)).unwrap();
+        assert!(!watcher.reload_requested());
+
+        // Other files in the same directory are ignored.
+        dir.write("other.toml", "");
+        assert!(!watcher.reload_requested());
+
+        fs::write(&path, "update_interval = 500\n").unwrap();
+        assert!(watcher.reload_requested());
+        assert!(!watcher.reload_requested());
+
+        // Saving by replacing the file, as many editors do.
+        fs::rename(dir.write("config.toml.tmp", ""), &path).unwrap();
+        assert!(watcher.reload_requested());
+
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+        assert!(watcher.reload_requested());
+        assert!(!watcher.reload_requested());
+    }
+
+    #[test]
+    fn test_reload_watches_every_path() {
+        let dir = TempDir::new("reload-paths");
+        let user = dir.dir("user").join("config.toml");
+        let system = dir.dir("system").join("config.conf");
+        let mut watcher = ConfigWatcher::new(&[user.clone(), dir.path().join("missing/config.toml"), system.clone()]).unwrap();
+
+        // Names are only watched in their own directory
+        dir.write("user/config.conf", "");
+        assert!(!watcher.reload_requested());
+
+        dir.write("system/config.conf", "");
+        assert!(watcher.reload_requested());
+        dir.write("user/config.toml", "");
+        assert!(watcher.reload_requested());
+    }
+}
@@ -0,0 +1,366 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::{AppConfig, DeviceType, DisplayConfig};
+use crate::discovery::{self, SensorSpec, Unresolved};
+use crate::source::{self, SensorSource};
+
+/// A temperature sensor the user can pick in the setup wizard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// Full chip name, e.g. "amdgpu-pci-0300"
+    pub chip: String,
+    /// Chip name as matched by `device`, e.g. "k10temp"
+    pub device: String,
+    pub vendor_id: Option<String>,
+    pub device_id: Option<String>,
+    pub pci_address: Option<String>,
+    /// The chip's device as a /sys/devices/... path
+    pub hwmon_device: Option<PathBuf>,
+    pub label: String,
+    pub value: Option<f64>,
+}
+
+impl Candidate {
+    // The full chip name and where it sits, so identical cards can be told
+    // apart in the list
+    fn describe(&self) -> String {
+        let mut out = self.chip.clone();
+        if self.vendor_id.is_some() || self.device_id.is_some() {
+            out.push_str(&format!(
+                " [ID: {}:{}]",
+                self.vendor_id.as_deref().unwrap_or("*"), self.device_id.as_deref().unwrap_or("*")));
+        }
+        match (&self.pci_address, &self.hwmon_device) {
+            (Some(address), _) => out.push_str(&format!(" at {}", address)),
+            (None, Some(device)) => out.push_str(&format!(" at {}", device.display())),
+            (None, None) => {}
+        }
+        out.push_str(&format!(" {}", self.label));
+        if let Some(value) = self.value {
+            out.push_str(&format!(": {:.1}°C", value));
+        }
+        out
+    }
+
+    fn ids(&self) -> (Option<&str>, Option<&str>) {
+        (self.vendor_id.as_deref(), self.device_id.as_deref())
+    }
+
+    // Other chips that `device` alone would select as well
+    fn namesakes<'a>(&'a self, all: &'a [Candidate]) -> impl Iterator<Item = &'a Candidate> {
+        all.iter().filter(move |c| c.device == self.device && c.chip != self.chip)
+    }
+
+    // IDs are only needed when another chip has the same name, and only help
+    // if this chip has some
+    fn needs_ids(&self, all: &[Candidate]) -> bool {
+        self.ids() != (None, None) && self.namesakes(all).any(|c| c.ids() != self.ids())
+    }
+
+    // Where the chip is plugged in is needed when the IDs can't tell it from
+    // another chip with the same name, e.g. with two identical GPUs or
+    // coolers: the PCI address, or else the device path
+    fn needs_location(&self, all: &[Candidate]) -> bool {
+        self.namesakes(all).any(|c| self.ids() == (None, None) || c.ids() == self.ids())
+    }
+}
+
+/// Every temperature sensor the source knows about.
+pub fn candidates(sensors: &dyn SensorSource) -> Vec<Candidate> {
+    sensors.chips().into_iter()
+        .flat_map(|chip| {
+            let hwmon_device = device_path(sensors.sysfs_root(), &chip.path);
+            chip.temperatures.into_iter().map(move |sensor| Candidate {
+                chip: chip.name.clone(),
+                device: chip.prefix.to_lowercase(),
+                vendor_id: chip.vendor_id.as_ref().map(|id| id.to_lowercase()),
+                device_id: chip.device_id.as_ref().map(|id| id.to_lowercase()),
+                pci_address: chip.pci_address.clone(),
+                hwmon_device: hwmon_device.clone(),
+                label: sensor.label,
+                value: sensor.input,
+            })
+        })
+        .collect()
+}
+
+// The chip's device as it's written in the config, under /sys wherever
+// sysfs is mounted
+fn device_path(sysfs_root: &Path, hwmon_path: &Path) -> Option<PathBuf> {
+    let device = fs::canonicalize(hwmon_path.join("device")).ok()?;
+    let rest = device.strip_prefix(fs::canonicalize(sysfs_root).ok()?).ok()?;
+    Some(Path::new(source::SYSFS_ROOT).join(rest))
+}
+
+/// Asks for the CPU and GPU sensors and writes the config to `path`.
+pub fn run(sensors: &dyn SensorSource, path: &Path, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let candidates = candidates(sensors);
+    if candidates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No temperature sensors found. Make sure lm-sensors is installed and 'sensors' shows your CPU and GPU"
+        ));
+    }
+
+    writeln!(output, "Available temperature sensors:")?;
+    writeln!(output, "-----------------------------")?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        writeln!(output, "{:>3}) {}", i + 1, candidate.describe())?;
+    }
+    writeln!(output, "-----------------------------\n")?;
+
+    let cpu = &candidates[choose(input, output, "CPU sensor", candidates.len())?];
+    let gpu = &candidates[choose(input, output, "GPU sensor", candidates.len())?];
+
+    let config_str = render_config(Some(cpu), Some(gpu), &candidates);
+    let config = AppConfig::parse_toml(&config_str)?;
+    for display in &config.displays {
+        for (device_type, role, chosen) in [(DeviceType::Cpu, "CPU", cpu), (DeviceType::Gpu, "GPU", gpu)] {
+            check_picks(sensors, display, device_type, chosen).map_err(|problem| io::Error::other(format!(
+                "The config for the chosen {} sensor {}, nothing was written", role, problem)))?;
+        }
+    }
+
+    if path.exists() && !confirm(input, output, &format!("{} already exists. Overwrite it?", path.display()))? {
+        writeln!(output, "Nothing was written.")?;
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, config_str).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => io::Error::new(
+            e.kind(),
+            format!("Permission denied writing {}, try again with sudo", path.display())
+        ),
+        _ => e,
+    })?;
+    writeln!(output, "\nWrote {}.", path.display())?;
+    Ok(())
+}
+
+// Checks that the config resolves the role to exactly the chosen sensor, the
+// same way the daemon will
+fn check_picks(sensors: &dyn SensorSource, display: &DisplayConfig, device_type: DeviceType, chosen: &Candidate) -> Result<(), String> {
+    let candidates = discovery::explain(sensors, &SensorSpec::of(display, device_type));
+    match discovery::pick(&candidates) {
+        Ok(i) if candidates[i].chip == chosen.chip && candidates[i].label == chosen.label => Ok(()),
+        Ok(i) => Err(format!("picks {} instead", candidates[i])),
+        Err(Unresolved::NotFound) => Err("finds no sensor".to_string()),
+        Err(Unresolved::Ambiguous(matches)) => Err(format!(
+            "is ambiguous, it matches {}",
+            matches.iter().map(|&i| candidates[i].to_string()).collect::<Vec<_>>().join(", "))),
+    }
+}
+
+// Asks for a number from 1 to `count` until one is given, returning its index
+fn choose(input: &mut impl BufRead, output: &mut impl Write, what: &str, count: usize) -> io::Result<usize> {
+    loop {
+        write!(output, "Pick the {} [1-{}]: ", what, count)?;
+        output.flush()?;
+
+        let line = read_line(input)?;
+        match line.trim().parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(n - 1),
+            _ => writeln!(output, "Please enter a number from 1 to {}.", count)?,
+        }
+    }
+}
+
+fn confirm(input: &mut impl BufRead, output: &mut impl Write, question: &str) -> io::Result<bool> {
+    write!(output, "{} [y/N]: ", question)?;
+    output.flush()?;
+    Ok(matches!(read_line(input)?.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn read_line(input: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Setup cancelled"));
+    }
+    Ok(line)
+}
+
+/// The config for the chosen sensors, adding IDs, the PCI address or the
+/// device path where the chip name alone would be ambiguous. A role without
+/// a sensor gets an empty `device`, leaving its digits blank.
+pub fn render_config(cpu: Option<&Candidate>, gpu: Option<&Candidate>, all: &[Candidate]) -> String {
+    let section = |comment: &str, table: &str, c: Option<&Candidate>| {
+        let mut out = format!("\n# {}\n[{}]\n", comment, table);
+        let Some(c) = c else {
+            out.push_str("device = \"\"\ntemp_type = \"\"\n");
+            return out;
+        };
+        out.push_str(&format!("device = {}\n", toml::Value::from(c.device.as_str())));
+        out.push_str(&format!("temp_type = {}\n", toml::Value::from(c.label.to_lowercase())));
+        if c.needs_ids(all) {
+            for (key, id) in [("vendor_id", &c.vendor_id), ("device_id", &c.device_id)] {
+                if let Some(id) = id {
+                    out.push_str(&format!("{} = {}\n", key, toml::Value::from(id.as_str())));
+                }
+            }
+        }
+        if c.needs_location(all) {
+            match (&c.pci_address, &c.hwmon_device) {
+                (Some(address), _) => out.push_str(&format!("pci_address = {}\n", toml::Value::from(address.as_str()))),
+                (None, Some(device)) => {
+                    out.push_str(&format!("hwmon_device = {}\n", toml::Value::from(device.to_string_lossy().as_ref())));
+                }
+                // Nothing tells it apart, which `run` reports
+                (None, None) => {}
+            }
+        }
+        out
+    };
+
+    let mut out = String::from("# Update interval in milliseconds\nupdate_interval = 1000\n");
+    out.push_str(&section("CPU device for temperature monitoring", "cpu", cpu));
+    out.push_str(&section("GPU device for temperature monitoring", "gpu", gpu));
+    out
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+
+    /// A sensor on `chip`, e.g. "amdgpu-pci-0300", whose device is the part
+    /// before the first dash.
+    pub fn candidate(chip: &str, ids: Option<(&str, &str)>, label: &str) -> Candidate {
+        Candidate {
+            chip: chip.to_string(),
+            device: chip.split('-').next().unwrap().to_string(),
+            vendor_id: ids.map(|(vendor_id, _)| vendor_id.to_string()),
+            device_id: ids.map(|(_, device_id)| device_id.to_string()),
+            pci_address: None,
+            hwmon_device: None,
+            label: label.to_string(),
+            value: Some(40.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::mock::candidate;
+    use crate::fixture::TempDir;
+    use crate::listing::{ChipInfo, SensorInfo};
+    use crate::source::mock::MockSource;
+
+    #[test]
+    fn test_choose_repeats_until_valid() {
+        let mut input = io::Cursor::new("\nabc\n0\n3\n2\n");
+        let mut output = Vec::new();
+
+        assert_eq!(1, choose(&mut input, &mut output, "CPU sensor", 2).unwrap());
+        assert_eq!(4, String::from_utf8(output).unwrap().matches("Please enter a number from 1 to 2.").count());
+
+        let err = choose(&mut input, &mut Vec::new(), "GPU sensor", 2).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn test_describe_tells_identical_chips_apart() {
+        let mut first = candidate("amdgpu-pci-0300", Some(("1002", "7550")), "edge");
+        first.pci_address = Some("0000:03:00.0".to_string());
+        let mut second = candidate("amdgpu-pci-0400", Some(("1002", "7550")), "edge");
+        second.pci_address = Some("0000:04:00.0".to_string());
+        assert_eq!("amdgpu-pci-0300 [ID: 1002:7550] at 0000:03:00.0 edge: 40.0°C", first.describe());
+        assert_eq!("amdgpu-pci-0400 [ID: 1002:7550] at 0000:04:00.0 edge: 40.0°C", second.describe());
+
+        // Chips off the PCI bus go by their device path
+        let mut sensor = candidate("corsaircpro-hid-3-1", None, "temp1");
+        sensor.hwmon_device = Some(PathBuf::from("/sys/devices/pci0000:00/usb1/1-3/1-3:1.0"));
+        assert_eq!("corsaircpro-hid-3-1 at /sys/devices/pci0000:00/usb1/1-3/1-3:1.0 temp1: 40.0°C", sensor.describe());
+    }
+
+    #[test]
+    fn test_render_config() {
+        let k10temp = candidate("k10temp-pci-00c3", Some(("1022", "14e3")), "Tctl");
+        let igpu = candidate("amdgpu-pci-0e00", Some(("1002", "164e")), "edge");
+        let dgpu = candidate("amdgpu-pci-0300", Some(("1002", "7550")), "edge");
+
+        // Unique names are enough on their own.
+        let config = render_config(Some(&k10temp), Some(&dgpu), &[k10temp.clone(), dgpu.clone()]);
+        assert_eq!(r#"# Update interval in milliseconds
+update_interval = 1000
+
+# CPU device for temperature monitoring
+[cpu]
+device = "k10temp"
+temp_type = "tctl"
+
+# GPU device for temperature monitoring
+[gpu]
+device = "amdgpu"
+temp_type = "edge"
+"#, config);
+
+        // Two amdgpu chips need the PCI IDs to tell them apart.
+        let config = render_config(Some(&k10temp), Some(&dgpu), &[k10temp.clone(), igpu, dgpu.clone()]);
+        let display = AppConfig::parse_toml(&config).unwrap().displays.remove(0);
+        assert!(display.cpu_ids.is_empty());
+        assert_eq!((Some(0x1002), Some(0x7550)), (display.gpu_ids.vendor, display.gpu_ids.device));
+        assert_eq!(None, display.gpu_pci_address);
+
+        // Identical GPUs only differ by where they are plugged in.
+        let at = |chip: &str, address: &str| Candidate {
+            chip: chip.to_string(), pci_address: Some(address.to_string()), ..dgpu.clone()
+        };
+        let config = render_config(Some(&k10temp), Some(&at("amdgpu-pci-0c00", "0000:0c:00.0")), &[k10temp.clone(), at("amdgpu-pci-0300", "0000:03:00.0"), at("amdgpu-pci-0c00", "0000:0c:00.0")]);
+        let display = AppConfig::parse_toml(&config).unwrap().displays.remove(0);
+        assert!(display.gpu_ids.is_empty());
+        assert_eq!(Some("0000:0c:00.0"), display.gpu_pci_address.as_deref());
+
+        // Identical USB coolers have no PCI address, so their device paths
+        // pin them, and chips without IDs never get empty ones.
+        let cooler = |chip: &str, device: &str| Candidate {
+            hwmon_device: Some(PathBuf::from(device)),
+            ..candidate(chip, Some(("1e71", "2007")), "Coolant temp")
+        };
+        let usb = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-10/1-10:1.0/0003:1E71:2007.0002";
+        let nvme = |chip: &str| candidate(chip, None, "Composite");
+        let all = [
+            cooler("nzxtkraken3-hid-3-1", "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-9/1-9:1.0/0003:1E71:2007.0001"),
+            cooler("nzxtkraken3-hid-3-2", usb),
+            Candidate { hwmon_device: Some(PathBuf::from("/sys/devices/virtual/nvme-subsystem/nvme-subsys1/nvme1")), ..nvme("nvme-pci-0200") },
+            nvme("nvme-pci-0100"),
+        ];
+        let config = render_config(Some(&all[2]), Some(&all[1]), &all);
+        assert!(!config.contains("_id"), "{}", config);
+        let display = AppConfig::parse_toml(&config).unwrap().displays.remove(0);
+        assert_eq!(Some(Path::new("/sys/devices/virtual/nvme-subsystem/nvme-subsys1/nvme1")), display.cpu_hwmon_device.as_deref());
+        assert_eq!(Some(Path::new(usb)), display.gpu_hwmon_device.as_deref());
+    }
+
+    #[test]
+    fn test_run_writes_nothing_when_the_choice_is_ambiguous() {
+        let cooler = |name: &str| {
+            let mut chip = ChipInfo::new(name.to_string(), "nzxtkraken3".to_string(), PathBuf::from("/nonexistent").join(name));
+            chip.temperatures.push(SensorInfo {
+                label: "Coolant temp".to_string(),
+                feature: "temp1".to_string(),
+                input: Some(30.0),
+                min: None,
+                max: None,
+                crit: None,
+                subfeatures: vec!["temp1_input".to_string()],
+            });
+            chip
+        };
+        let sensors = MockSource { chips: vec![cooler("nzxtkraken3-hid-3-1"), cooler("nzxtkraken3-hid-3-2")], ..Default::default() };
+        let dir = TempDir::new("setup");
+        let path = dir.path().join("config.toml");
+
+        let err = run(&sensors, &path, &mut io::Cursor::new("1\n2\n"), &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "The config for the chosen CPU sensor is ambiguous, it matches nzxtkraken3-hid-3-1 temp1 (Coolant temp), \
+             nzxtkraken3-hid-3-2 temp1 (Coolant temp), nothing was written",
+            err.to_string()
+        );
+        assert!(!path.exists());
+    }
+}
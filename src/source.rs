@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::listing::ChipInfo;
+#[cfg(feature = "libsensors")]
+use crate::libsensors::Libsensors;
+use crate::sysfs::Sysfs;
+
+/// Where temperatures are read from.
+///
+/// `Libsensors` goes through the lm-sensors library, which also applies
+/// `sensors.conf`. `Sysfs` reads the kernel's hwmon files itself, so a build
+/// without the `libsensors` feature doesn't link the library at all. Tests
+/// use `mock::MockSource`.
+pub trait SensorSource {
+    /// Every hwmon chip with its temperature sensors and their current values.
+    fn chips(&self) -> Vec<ChipInfo>;
+
+    /// Current temperature of a sensor found through `chips`, in °C.
+    fn read(&self, sensor: &SensorRef) -> Option<f64>;
+
+    /// Whether `chips` also finds chips that appeared after the source was
+    /// opened, e.g. after a driver reload.
+    fn rescans(&self) -> bool {
+        true
+    }
+
+    /// Where the sysfs tree the chips were found in is mounted, for looking
+    /// up their PCI devices.
+    fn sysfs_root(&self) -> &Path;
+}
+
+/// One temperature sensor, as remembered between reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorRef {
+    /// The chip's hwmon directory, e.g. "/sys/class/hwmon/hwmon2"
+    pub chip: PathBuf,
+    /// e.g. "temp1"
+    pub feature: String,
+}
+
+/// Where sysfs is normally mounted, and the only place libsensors looks.
+pub const SYSFS_ROOT: &str = "/sys";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    #[cfg(feature = "libsensors")]
+    Libsensors,
+    Sysfs,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        #[cfg(feature = "libsensors")]
+        return Backend::Libsensors;
+        #[cfg(not(feature = "libsensors"))]
+        return Backend::Sysfs;
+    }
+}
+
+/// Opens the chosen backend, or the default one. A sysfs root other than
+/// /sys picks the sysfs backend, as libsensors can't be pointed elsewhere.
+pub fn open(backend: Option<Backend>, sysfs_root: Option<&Path>) -> Result<Box<dyn SensorSource>, String> {
+    let moved_root = sysfs_root.filter(|root| *root != Path::new(SYSFS_ROOT));
+    let backend = match (backend, moved_root) {
+        (Some(backend), _) => backend,
+        (None, Some(_)) => Backend::Sysfs,
+        (None, None) => Backend::default(),
+    };
+
+    match backend {
+        #[cfg(feature = "libsensors")]
+        Backend::Libsensors if moved_root.is_some() => {
+            Err("--sysfs-root only works with the sysfs backend, libsensors always reads /sys".to_string())
+        }
+        #[cfg(feature = "libsensors")]
+        Backend::Libsensors => Ok(Box::new(Libsensors::new())),
+        Backend::Sysfs => Ok(Box::new(Sysfs::new(sysfs_root.unwrap_or(Path::new(SYSFS_ROOT))))),
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+
+    /// Fixed chips, whose values tests can change between reads.
+    #[derive(Debug, Default)]
+    pub struct MockSource {
+        pub chips: Vec<ChipInfo>,
+        /// A fixture directory standing in for /sys
+        pub root: PathBuf,
+    }
+
+    impl SensorSource for MockSource {
+        fn chips(&self) -> Vec<ChipInfo> {
+            self.chips.clone()
+        }
+
+        fn read(&self, sensor: &SensorRef) -> Option<f64> {
+            self.chips.iter()
+                .filter(|chip| chip.path == sensor.chip)
+                .flat_map(|chip| &chip.temperatures)
+                .find(|temperature| temperature.feature == sensor.feature)?
+                .input
+        }
+
+        fn sysfs_root(&self) -> &Path {
+            &self.root
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_open_with_sysfs_root() {
+        let sensors = open(None, Some(Path::new("/host/sys"))).unwrap();
+        assert_eq!(Path::new("/host/sys"), sensors.sysfs_root());
+        let sensors = open(Some(Backend::Sysfs), None).unwrap();
+        assert_eq!(Path::new(SYSFS_ROOT), sensors.sysfs_root());
+        assert!(sensors.rescans());
+
+        #[cfg(feature = "libsensors")]
+        assert!(open(Some(Backend::Libsensors), Some(Path::new("/host/sys"))).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::listing::{self, Bus, ChipInfo, SensorInfo};
+use crate::source::{SensorRef, SensorSource};
+
+/// Sensors read straight from the kernel's hwmon files, without libsensors.
+pub struct Sysfs {
+    // Where sysfs is mounted, normally /sys
+    root: PathBuf,
+}
+
+impl Sysfs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Sysfs { root: root.into() }
+    }
+}
+
+impl SensorSource for Sysfs {
+    fn chips(&self) -> Vec<ChipInfo> {
+        let Ok(entries) = fs::read_dir(self.root.join("class/hwmon")) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        // hwmon2 before hwmon10, like libsensors
+        paths.sort_by_key(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            (name.trim_start_matches("hwmon").parse::<u32>().unwrap_or(u32::MAX), name.to_string())
+        });
+
+        paths.into_iter()
+            .filter_map(|path| {
+                let prefix = read_trimmed(&path.join("name"))?;
+                let mut info = ChipInfo::new(chip_name(&prefix, &path), prefix, path.clone());
+                info.temperatures = temperatures(&path);
+                Some(info)
+            })
+            .collect()
+    }
+
+    fn read(&self, sensor: &SensorRef) -> Option<f64> {
+        read_millidegrees(&sensor.chip.join(format!("{}_input", sensor.feature)))
+    }
+
+    fn sysfs_root(&self) -> &Path {
+        &self.root
+    }
+}
+
+// Chip name in libsensors' style, e.g. "amdgpu-pci-0300",
+// "nct6775-isa-0290" or "nzxtkraken3-hid-3-1"
+fn chip_name(prefix: &str, hwmon_path: &Path) -> String {
+    let bus = listing::bus(hwmon_path);
+    match bus_address(bus, hwmon_path) {
+        Some(address) => format!("{}-{}-{}", prefix, bus.libsensors_name(), address),
+        None => format!("{}-virtual-0", prefix),
+    }
+}
+
+// The part of the chip name after the bus type, worked out from the parent
+// device's name the way libsensors does
+fn bus_address(bus: Bus, hwmon_path: &Path) -> Option<String> {
+    let device = listing::device_name(hwmon_path);
+    match bus {
+        Bus::Pci => Some(format!("{:04x}", listing::libsensors_bus_address(&listing::pci_address(hwmon_path)?)?)),
+        // The number after the dot is the I/O port in decimal, e.g.
+        // "nct6775.656". Other platform devices count up from 0.
+        Bus::Isa | Bus::Platform => {
+            let port = device?.rsplit_once('.').and_then(|(_, port)| port.parse::<u16>().ok()).unwrap_or(0);
+            Some(format!("{:04x}", port))
+        }
+        // libsensors assumes there's one of each ACPI chip
+        Bus::Acpi => Some("0".to_string()),
+        Bus::Hid => {
+            let hid = listing::parse_hid_name(&device?)?;
+            Some(format!("{}-{:x}", hid.bus_type, hid.instance))
+        }
+        // Adapter number and address, e.g. "0-0048"
+        Bus::I2c => {
+            let device = device?;
+            let (adapter, address) = device.split_once('-')?;
+            Some(format!("{}-{:02x}", adapter.parse::<u16>().ok()?, u16::from_str_radix(address, 16).ok()?))
+        }
+        // Controller and chip select, e.g. "spi0.1"
+        Bus::Spi => {
+            let device = device?;
+            let (controller, chip_select) = device.strip_prefix("spi")?.split_once('.')?;
+            Some(format!("{}-{:x}", controller.parse::<u16>().ok()?, chip_select.parse::<u16>().ok()?))
+        }
+        Bus::Virtual => None,
+    }
+}
+
+// The temperature features of a hwmon directory, from its tempN_* files
+fn temperatures(hwmon_path: &Path) -> Vec<SensorInfo> {
+    let Ok(entries) = fs::read_dir(hwmon_path) else {
+        return Vec::new();
+    };
+
+    // Subfeature file names by feature number, sorted
+    let mut features: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for entry in entries.flatten() {
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let Some((number, _)) = file_name.strip_prefix("temp").and_then(|rest| rest.split_once('_')) else {
+            continue;
+        };
+        let Ok(number) = number.parse() else {
+            continue;
+        };
+        features.entry(number).or_default().push(file_name);
+    }
+
+    features.into_iter()
+        .map(|(number, mut files)| {
+            let feature = format!("temp{}", number);
+            let value = |suffix: &str| read_millidegrees(&hwmon_path.join(format!("{}_{}", feature, suffix)));
+            files.retain(|file| !file.ends_with("_label"));
+            files.sort();
+            SensorInfo {
+                // Unlabelled sensors go by the feature name, as in libsensors
+                label: read_trimmed(&hwmon_path.join(format!("{}_label", feature))).unwrap_or_else(|| feature.clone()),
+                input: value("input"),
+                min: value("min"),
+                max: value("max"),
+                crit: value("crit"),
+                subfeatures: files,
+                feature,
+            }
+        })
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+// hwmon reports temperatures in millidegrees Celsius
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    read_trimmed(path)?.parse::<i64>().ok().map(|millidegrees| millidegrees as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempDir;
+
+    #[test]
+    fn test_chips_and_read() {
+        let root = TempDir::new("sysfs");
+        let gpu_hwmon = root.hwmon(&root.device("pci0000:00/0000:00:01.1/0000:0c:01.2", Some("pci")), "hwmon10");
+        // Virtual hwmon devices have no device link
+        let nvme_hwmon = root.dir("devices/virtual/hwmon/hwmon2");
+        root.symlink(&nvme_hwmon, "class/hwmon/hwmon2");
+
+        for (file, contents) in [
+            ("name", "amdgpu\n"),
+            ("temp1_input", "45000\n"),
+            ("temp1_label", "edge\n"),
+            ("temp1_crit", "100000\n"),
+            ("temp2_input", "51500\n"),
+            ("temp2_label", "junction\n"),
+            ("power1_input", "30000000\n"),
+        ] {
+            root.write(gpu_hwmon.join(file), contents);
+        }
+        root.write(nvme_hwmon.join("name"), "nvme\n");
+        root.write(nvme_hwmon.join("temp1_input"), "-5000\n");
+
+        let sysfs = Sysfs::new(root.path());
+        let chips = sysfs.chips();
+
+        let [nvme, amdgpu] = &chips[..] else {
+            panic!("expected two chips, got {:?}", chips);
+        };
+        assert_eq!(("nvme-virtual-0", "nvme"), (nvme.name.as_str(), nvme.prefix.as_str()));
+        assert_eq!("temp1", nvme.temperatures[0].label);
+        assert_eq!(Some(-5.0), nvme.temperatures[0].input);
+
+        // Bus 0x0c, device 1, function 2
+        assert_eq!("amdgpu-pci-0c0a", amdgpu.name);
+        assert_eq!(Some("0000:0c:01.2"), amdgpu.pci_address.as_deref());
+        let [edge, junction] = &amdgpu.temperatures[..] else {
+            panic!("expected two temperatures, got {:?}", amdgpu.temperatures);
+        };
+        assert_eq!(("edge", "temp1", Some(45.0), Some(100.0)), (edge.label.as_str(), edge.feature.as_str(), edge.input, edge.crit));
+        assert_eq!(vec!["temp1_crit", "temp1_input"], edge.subfeatures);
+        assert_eq!(("junction", Some(51.5), None), (junction.label.as_str(), junction.input, junction.max));
+
+        let sensor = SensorRef { chip: amdgpu.path.clone(), feature: "temp2".to_string() };
+        assert_eq!(Some(51.5), sysfs.read(&sensor));
+        fs::write(gpu_hwmon.join("temp2_input"), "60000\n").unwrap();
+        assert_eq!(Some(60.0), sysfs.read(&sensor));
+        fs::remove_file(gpu_hwmon.join("temp2_input")).unwrap();
+        assert_eq!(None, sysfs.read(&sensor));
+    }
+
+    #[test]
+    fn test_chip_names_by_bus() {
+        let root = TempDir::new("buses");
+        // Device under /sys/devices, its subsystem, and the name libsensors gives its chip
+        let cases = [
+            ("platform/nct6775.656", "platform", "nct6775", "nct6775-isa-0290"),
+            ("platform/coretemp.0", "platform", "coretemp", "coretemp-isa-0000"),
+            ("LNXSYSTM:00/LNXTHERM:00", "acpi", "acpitz", "acpitz-acpi-0"),
+            ("pci0000:00/0000:00:14.0/usb1/1-9/1-9:1.0/0003:1E71:2007.000B", "hid", "nzxtkraken3", "nzxtkraken3-hid-3-b"),
+            ("pci0000:00/0000:00:1f.4/i2c-0/0-0018", "i2c", "jc42", "jc42-i2c-0-18"),
+            ("platform/spi_master/spi1/spi1.2", "spi", "lm70", "lm70-spi-1-2"),
+            ("pci0000:00/0000:00:01.2/0000:02:00.0/nvme/nvme0", "nvme", "nvme", "nvme-virtual-0"),
+        ];
+        let hwmons: Vec<PathBuf> = cases.iter().enumerate()
+            .map(|(i, (device, subsystem, _, _))| root.hwmon(&root.device(device, Some(subsystem)), &format!("hwmon{}", i)))
+            .collect();
+
+        for (hwmon, (_, _, prefix, name)) in hwmons.iter().zip(&cases) {
+            assert_eq!(*name, chip_name(prefix, hwmon));
+        }
+
+        let kraken = ChipInfo::new("nzxtkraken3-hid-3-b".to_string(), "nzxtkraken3".to_string(), hwmons[3].clone());
+        assert_eq!(Bus::Hid, kraken.bus);
+        assert_eq!((Some("1e71"), Some("2007")), (kraken.vendor_id.as_deref(), kraken.device_id.as_deref()));
+        assert_eq!(None, kraken.pci_address);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::protocol::Temperatures;
+use crate::usb::{UsbError, UsbLocation, UsbTarget};
+
+/// A link to the case display that frames can be written to.
+///
+/// `UsbDevice` is the real implementation. Tests use `mock::MockTransport`,
+/// which records every frame instead of writing it to hardware.
+pub trait DisplayTransport {
+    fn open(target: &UsbTarget) -> Result<Self, UsbError>
+    where
+        Self: Sized;
+
+    /// Where the opened device sits on the bus, if the transport knows.
+    fn location(&self) -> Option<UsbLocation>;
+
+    fn claim_interface(&mut self) -> Result<(), UsbError>;
+
+    fn send_frame(&mut self, payload: &[u8]) -> Result<(), UsbError>;
+
+    fn send_payload(&mut self, temperatures: &Temperatures) -> Result<(), UsbError> {
+        self.send_frame(&temperatures.to_frame().encode())
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    thread_local! {
+        static OPEN_ERRORS: RefCell<VecDeque<UsbError>> = const { RefCell::new(VecDeque::new()) };
+        static NEXT_ADDRESS: Cell<u8> = const { Cell::new(1) };
+    }
+
+    /// Makes the next `MockTransport::open` on this thread fail with `e`.
+    pub fn fail_next_open(e: UsbError) {
+        OPEN_ERRORS.with(|errors| errors.borrow_mut().push_back(e));
+    }
+
+    /// In-memory transport which records every frame sent to it.
+    #[derive(Debug, Default)]
+    pub struct MockTransport {
+        pub target: UsbTarget,
+        /// Every opened mock gets its own address on bus 1.
+        pub location: Option<UsbLocation>,
+        pub claims: usize,
+        pub frames: Vec<Vec<u8>>,
+        /// Errors returned by the next calls to `send_frame`, in order.
+        pub send_errors: VecDeque<UsbError>,
+    }
+
+    impl DisplayTransport for MockTransport {
+        fn open(target: &UsbTarget) -> Result<Self, UsbError> {
+            if let Some(e) = OPEN_ERRORS.with(|errors| errors.borrow_mut().pop_front()) {
+                return Err(e);
+            }
+            let address = NEXT_ADDRESS.with(|next| next.replace(next.get().wrapping_add(1)));
+            Ok(Self {
+                target: target.clone(),
+                location: Some(UsbLocation { bus: 1, address }),
+                ..Default::default()
+            })
+        }
+
+        fn location(&self) -> Option<UsbLocation> {
+            self.location
+        }
+
+        fn claim_interface(&mut self) -> Result<(), UsbError> {
+            self.claims += 1;
+            Ok(())
+        }
+
+        fn send_frame(&mut self, payload: &[u8]) -> Result<(), UsbError> {
+            if let Some(e) = self.send_errors.pop_front() {
+                return Err(e);
+            }
+            self.frames.push(payload.to_vec());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::mock::MockTransport;
+    use super::*;
+    use crate::protocol::{Overflow, Resolution, generate_payload};
+
+    fn temperatures(cpu_temp: Option<f64>, gpu_temp: Option<f64>) -> Temperatures {
+        Temperatures::from_degrees(&cpu_temp, &gpu_temp, Resolution::Tenths, Overflow::Clamp)
+    }
+
+    #[test]
+    fn test_mock_records_frames() {
+        let mut device = MockTransport::open(&UsbTarget::default()).unwrap();
+        device.claim_interface().unwrap();
+        device.send_payload(&temperatures(Some(24.0), Some(16.0))).unwrap();
+        device.send_payload(&temperatures(Some(24.0), None)).unwrap();
+
+        assert_eq!(UsbTarget::default(), device.target);
+        assert_eq!(1, device.claims);
+        assert_eq!(
+            vec![
+                generate_payload(&Some(24.0), &Some(16.0)),
+                generate_payload(&Some(24.0), &None),
+            ],
+            device.frames
+        );
+    }
+
+    #[test]
+    fn test_mock_returns_queued_errors() {
+        let mut device = MockTransport::open(&UsbTarget::default()).unwrap();
+        device.send_errors.push_back(UsbError::WriteTimeout);
+
+        assert_eq!(Err(UsbError::WriteTimeout), device.send_payload(&temperatures(Some(24.0), None)));
+        assert_eq!(Ok(()), device.send_payload(&temperatures(Some(24.0), None)));
+        assert_eq!(1, device.frames.len());
+    }
+}
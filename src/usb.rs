@@ -1,140 +1,247 @@
 // Written by nishtahir
 // https://github.com/nishtahir/antec-flux-pro-display/blob/main/src/usb.rs
+use std::fmt;
 use std::time::Duration;
 
-use anyhow::Result;
+use crate::transport::DisplayTransport;
 
 pub const VENDOR_ID: u16 = 0x2022;
 pub const PRODUCT_ID: u16 = 0x0522;
 
-pub struct UsbDevice {
-    handle: rusb::DeviceHandle<rusb::GlobalContext>,
+// This appears to be the correct endpoint on my machine
+// Seems reasonable as the default
+const DEFAULT_ENDPOINT: u8 = 0x03;
+
+/// Identifies the display to open and how to talk to it.
+///
+/// The optional selectors pick one display when several with the same
+/// vendor/product ID are connected. Without them the first match is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbTarget {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// USB bus number, as shown by `lsusb`.
+    pub bus: Option<u8>,
+    /// Port numbers from the root hub down, e.g. `[1, 4]` for `1-1.4` in
+    /// /sys/bus/usb/devices.
+    pub port: Option<Vec<u8>>,
+    /// iSerial string reported by the device.
+    pub serial: Option<String>,
+    /// Interrupt OUT endpoint to write frames to. Discovered from the
+    /// config descriptor when `None`.
+    pub endpoint: Option<u8>,
 }
 
-impl UsbDevice {
-    pub fn open(vendor_id: u16, product_id: u16) -> Result<Self> {
-        match rusb::open_device_with_vid_pid(vendor_id, product_id) {
-            Some(handle) => Ok(Self { handle }),
-            None => {
-                // Check if device is visible at all
-                let devices = match rusb::devices() {
-                    Ok(devices) => devices,
-                    Err(e) => {
-                        eprintln!("Error getting devices: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-
-                for device in devices.iter() {
-                    let device_desc = match device.device_descriptor() {
-                        Ok(desc) => desc,
-                        Err(e) => {
-                            eprintln!("Error getting device descriptor: {}", e);
-                            std::process::exit(1);
-                        }
-                    };
-
-                    if device_desc.vendor_id() == VENDOR_ID
-                        && device_desc.product_id() == PRODUCT_ID
-                    {
-                        eprintln!("Permission denied accessing USB device.");
-                        eprintln!("Please ensure udev rules are properly configured.");
-                        std::process::exit(1);
-                    }
-                }
-                eprintln!("USB device not found. Is it connected?");
-                eprintln!("Looking for device {:04x}:{:04x}", VENDOR_ID, PRODUCT_ID);
-                std::process::exit(1);
-            }
+impl Default for UsbTarget {
+    fn default() -> Self {
+        Self {
+            vendor_id: VENDOR_ID,
+            product_id: PRODUCT_ID,
+            bus: None,
+            port: None,
+            serial: None,
+            endpoint: None,
         }
     }
+}
 
-    pub fn claim_interface(&self) {
-        // Free the interface if its active already, then claim it.
-        if let Ok(active) = self.handle.kernel_driver_active(0) {
-            if active {
-                self.handle.detach_kernel_driver(0).ok();
-            }
+impl UsbTarget {
+    /// Whether both targets could pick the same display, i.e. they have the
+    /// same IDs and no selector set on both tells them apart.
+    pub fn overlaps(&self, other: &UsbTarget) -> bool {
+        fn differ<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            matches!((a, b), (Some(a), Some(b)) if a != b)
         }
-        match self.handle.claim_interface(0) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Failed to claim interface: {}", e);
-                std::process::exit(1);
-            }
+        (self.vendor_id, self.product_id) == (other.vendor_id, other.product_id)
+            && !differ(&self.bus, &other.bus)
+            && !differ(&self.port, &other.port)
+            && !differ(&self.serial, &other.serial)
+    }
+
+    // Checks everything except the serial, which needs an open handle
+    fn matches(&self, device: &rusb::Device<rusb::GlobalContext>) -> bool {
+        let Ok(desc) = device.device_descriptor() else {
+            return false;
+        };
+
+        desc.vendor_id() == self.vendor_id
+            && desc.product_id() == self.product_id
+            && self.bus.is_none_or(|bus| bus == device.bus_number())
+            && self.port.as_ref().is_none_or(|port| device.port_numbers().is_ok_and(|p| p == *port))
+    }
+}
 
+impl fmt::Display for UsbTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?;
+        if let Some(bus) = self.bus {
+            write!(f, " bus {}", bus)?;
+        }
+        if let Some(port) = &self.port {
+            let port: Vec<String> = port.iter().map(|p| p.to_string()).collect();
+            write!(f, " port {}", port.join("."))?;
         }
+        if let Some(serial) = &self.serial {
+            write!(f, " serial {}", serial)?;
+        }
+        Ok(())
     }
+}
 
-    pub fn send_payload(&self, cpu_temp: &Option<f64>, gpu_temp: &Option<f64>) {
-        let payload = generate_payload(cpu_temp, gpu_temp);
+/// Where a device currently sits on the USB tree. Addresses are reassigned
+/// on every reconnect, so this identifies one connection of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbLocation {
+    pub bus: u8,
+    pub address: u8,
+}
 
-        let config_desc = match self.handle.device().config_descriptor(0) {
-            Ok(desc) => desc,
-            Err(e) => {
-                eprintln!("Error getting config descriptor: {}", e);
-                std::process::exit(1);
-            }
-        };
+impl UsbLocation {
+    pub fn of(device: &rusb::Device<rusb::GlobalContext>) -> Self {
+        Self {
+            bus: device.bus_number(),
+            address: device.address(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbError {
+    /// No device with the requested vendor/product ID is connected.
+    NotFound { vendor_id: u16, product_id: u16 },
+    /// The device is connected but we are not allowed to open it.
+    PermissionDenied { vendor_id: u16, product_id: u16 },
+    /// The display interface could not be claimed.
+    ClaimFailed(rusb::Error),
+    /// The device did not accept a frame in time.
+    WriteTimeout,
+    /// The device went away while it was in use.
+    Disconnected,
+    /// Any other libusb failure.
+    Other(rusb::Error),
+}
+
+impl From<rusb::Error> for UsbError {
+    fn from(e: rusb::Error) -> Self {
+        match e {
+            rusb::Error::Timeout => UsbError::WriteTimeout,
+            rusb::Error::NoDevice => UsbError::Disconnected,
+            e => UsbError::Other(e),
+        }
+    }
+}
+
+impl fmt::Display for UsbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsbError::NotFound { vendor_id, product_id } => write!(
+                f,
+                "USB device not found. Is it connected?\nLooking for device {:04x}:{:04x}",
+                vendor_id, product_id
+            ),
+            UsbError::PermissionDenied { vendor_id, product_id } => write!(
+                f,
+                "Permission denied accessing USB device {:04x}:{:04x}.\nPlease ensure udev rules are properly configured.",
+                vendor_id, product_id
+            ),
+            UsbError::ClaimFailed(e) => write!(f, "Failed to claim interface: {}", e),
+            UsbError::WriteTimeout => write!(f, "Timed out writing to USB device"),
+            UsbError::Disconnected => write!(f, "USB device was disconnected"),
+            UsbError::Other(e) => write!(f, "USB error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UsbError {}
+
+pub struct UsbDevice {
+    handle: rusb::DeviceHandle<rusb::GlobalContext>,
+    endpoint: u8,
+}
+
+impl DisplayTransport for UsbDevice {
+    fn open(target: &UsbTarget) -> Result<Self, UsbError> {
+        let mut open_errors = Vec::new();
+
+        for device in rusb::devices()?.iter().filter(|device| target.matches(device)) {
+            let handle = match device.open() {
+                Ok(handle) => handle,
+                Err(e) => {
+                    open_errors.push(e);
+                    continue;
+                }
+            };
 
-        // Find the first interrupt OUT endpoint
-        let endpoint_address = config_desc
-            .interfaces()
-            .flat_map(|interface| interface.descriptors())
-            .flat_map(|desc| desc.endpoint_descriptors())
-            .find(|endpoint| {
-                endpoint.transfer_type() == rusb::TransferType::Interrupt
-                    && endpoint.direction() == rusb::Direction::Out
-            })
-            .map(|endpoint| endpoint.address())
-            // This appears to be the correct endpoint on my machine
-            // Seems reasonable as the default
-            .unwrap_or(0x03);
-
-        match self
-            .handle
-            .write_interrupt(endpoint_address, &payload, Duration::from_millis(1000))
-        {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Error writing interrupt: {:?}", e);
-                std::process::exit(1);
+            if let Some(serial) = &target.serial {
+                let found = device.device_descriptor()
+                    .and_then(|desc| handle.read_serial_number_string_ascii(&desc));
+                if found.as_ref() != Ok(serial) {
+                    continue;
+                }
             }
+
+            let endpoint = match target.endpoint {
+                Some(endpoint) => endpoint,
+                None => find_interrupt_out_endpoint(&device)?,
+            };
+            return Ok(Self { handle, endpoint });
+        }
+
+        Err(open_failure(target, &open_errors))
+    }
+
+    fn location(&self) -> Option<UsbLocation> {
+        Some(UsbLocation::of(&self.handle.device()))
+    }
+
+    fn claim_interface(&mut self) -> Result<(), UsbError> {
+        // Free the interface if its active already, then claim it.
+        if self.handle.kernel_driver_active(0).unwrap_or(false) {
+            self.handle.detach_kernel_driver(0).ok();
         }
+        self.handle.claim_interface(0).map_err(|e| match e {
+            rusb::Error::NoDevice => UsbError::Disconnected,
+            e => UsbError::ClaimFailed(e),
+        })
+    }
+
+    fn send_frame(&mut self, payload: &[u8]) -> Result<(), UsbError> {
+        self.handle
+            .write_interrupt(self.endpoint, payload, Duration::from_millis(1000))?;
+        Ok(())
     }
 }
 
-fn generate_payload(cpu_temp: &Option<f64>, gpu_temp: &Option<f64>) -> Vec<u8> {
-    let mut payload = Vec::<u8>::new();
-    payload.push(85);
-    payload.push(170);
-    payload.push(1);
-    payload.push(1);
-    payload.push(6);
-
-    let encoded_temp = encode_temperature(cpu_temp);
-    payload.push(encoded_temp.0);
-    payload.push(encoded_temp.1);
-    payload.push(encoded_temp.2);
-
-    let encoded_temp = encode_temperature(gpu_temp);
-    payload.push(encoded_temp.0);
-    payload.push(encoded_temp.1);
-    payload.push(encoded_temp.2);
-
-    let checksum = payload.iter().fold(0u8, |acc, e| acc.wrapping_add(*e));
-    payload.push(checksum);
-    payload
+// Find the first interrupt OUT endpoint
+fn find_interrupt_out_endpoint(device: &rusb::Device<rusb::GlobalContext>) -> Result<u8, UsbError> {
+    let config_desc = device.config_descriptor(0)?;
+
+    let endpoint_address = config_desc
+        .interfaces()
+        .flat_map(|interface| interface.descriptors())
+        .flat_map(|desc| desc.endpoint_descriptors())
+        .find(|endpoint| {
+            endpoint.transfer_type() == rusb::TransferType::Interrupt
+                && endpoint.direction() == rusb::Direction::Out
+        })
+        .map(|endpoint| endpoint.address())
+        .unwrap_or(DEFAULT_ENDPOINT);
+
+    Ok(endpoint_address)
 }
 
-fn encode_temperature(temp: &Option<f64>) -> (u8, u8, u8) {
-    if let Some(temp) = temp {
-        let ones = (temp / 10.0) as u8;
-        let tens = (temp % 10.0) as u8;
-        let tenths = ((temp * 10.0) % 10.0) as u8;
-        return (ones, tens, tenths);
+// Why no device could be opened, given the errors of the ones that matched
+// but wouldn't open. Lacking permission is the likeliest fix, so it's
+// reported first.
+fn open_failure(target: &UsbTarget, open_errors: &[rusb::Error]) -> UsbError {
+    let UsbTarget { vendor_id, product_id, .. } = *target;
+    if open_errors.contains(&rusb::Error::Access) {
+        return UsbError::PermissionDenied { vendor_id, product_id };
+    }
+    match open_errors.last() {
+        Some(&e) => UsbError::Other(e),
+        None => UsbError::NotFound { vendor_id, product_id },
     }
-    (238, 238, 238)
 }
 
 #[cfg(test)]
@@ -142,16 +249,21 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_generate_payload() {
-        let actual = generate_payload(&Some(24.0), &Some(16.0));
-        let expected = vec![85, 170, 1, 1, 6, 2, 4, 0, 1, 6, 0, 20];
-        assert_eq!(expected, actual);
+    fn test_usb_error_from_rusb() {
+        assert_eq!(UsbError::WriteTimeout, UsbError::from(rusb::Error::Timeout));
+        assert_eq!(UsbError::Disconnected, UsbError::from(rusb::Error::NoDevice));
+        assert_eq!(UsbError::Other(rusb::Error::Pipe), UsbError::from(rusb::Error::Pipe));
     }
 
     #[test]
-    fn test_generate_payload_with_no_gpu() {
-        let actual = generate_payload(&Some(24.0), &None);
-        let expected = vec![85, 170, 1, 1, 6, 2, 4, 0, 238, 238, 238, 215];
-        assert_eq!(expected, actual);
+    fn test_open_failure_keeps_the_open_error() {
+        let target = UsbTarget::default();
+        let ids = (target.vendor_id, target.product_id);
+        assert_eq!(UsbError::NotFound { vendor_id: ids.0, product_id: ids.1 }, open_failure(&target, &[]));
+        assert_eq!(UsbError::Other(rusb::Error::Busy), open_failure(&target, &[rusb::Error::Io, rusb::Error::Busy]));
+        assert_eq!(
+            UsbError::PermissionDenied { vendor_id: ids.0, product_id: ids.1 },
+            open_failure(&target, &[rusb::Error::Access, rusb::Error::Busy])
+        );
     }
 }